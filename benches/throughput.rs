@@ -0,0 +1,148 @@
+//! Throughput of parsing, with the fast path and with serde, of `csv_reader`, of `parallel_feed`, of engine dispatch and of output
+//! over generated workloads.
+//!
+//! Row counts go from 1e4 up to `BENCH_MAX_ROWS` (1e5 by default, 1e8 at most), e.g.
+//! `BENCH_MAX_ROWS=100000000 cargo bench`. Inputs are generated in memory, 1e8 rows needing over 10 gigabytes.
+//! Compare against a saved run with `cargo bench -- --save-baseline <name>` then `cargo bench -- --baseline <name>`.
+
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use payment_engine::engine::{ClientId, ClientState, Engine, Transaction};
+use payment_engine::generate::{Generator, GeneratorConfig};
+use payment_engine::io::{csv_reader, csv_writer, transactions_writer, OutputOptions};
+use payment_engine::parallel::{parallel_feed, DEFAULT_BLOCK_SIZE};
+use payment_engine::parse::TransactionReader;
+
+const ROWS: [u64; 5] = [10_000, 100_000, 1_000_000, 10_000_000, 100_000_000];
+const DEFAULT_MAX_ROWS: u64 = 100_000;
+/// Rows of the workloads varying the client cardinality or the dispute ratio.
+const VARIANT_ROWS: u64 = 100_000;
+const CLIENTS: [ClientId; 3] = [10, 1_000, ClientId::MAX];
+const DISPUTE_RATIOS: [f64; 3] = [0.0, 0.01, 0.1];
+
+fn max_rows() -> u64 {
+    std::env::var("BENCH_MAX_ROWS")
+        .ok()
+        .map(|value| value.parse().expect("Error: BENCH_MAX_ROWS expects a number of rows"))
+        .unwrap_or(DEFAULT_MAX_ROWS)
+}
+
+/// Workloads of every size up to the maximum, then of varying client cardinality and dispute ratio.
+fn workloads() -> Vec<(&'static str, String, GeneratorConfig, u64)> {
+    let config = GeneratorConfig::default();
+    let max_rows = max_rows();
+    let sizes = ROWS
+        .into_iter()
+        .filter(|rows| *rows <= max_rows)
+        .map(|rows| ("rows", rows.to_string(), config, rows));
+    let clients = CLIENTS
+        .into_iter()
+        .map(|clients| ("clients", clients.to_string(), GeneratorConfig { clients, ..config }, VARIANT_ROWS));
+    let dispute_ratios = DISPUTE_RATIOS.into_iter().map(|dispute_ratio| {
+        ("dispute_ratio", dispute_ratio.to_string(), GeneratorConfig { dispute_ratio, ..config }, VARIANT_ROWS)
+    });
+    sizes.chain(clients).chain(dispute_ratios).collect()
+}
+
+fn transactions(config: GeneratorConfig, rows: u64) -> Vec<Transaction> {
+    Generator::new(config).take(rows as usize).collect()
+}
+
+fn csv_input(config: GeneratorConfig, rows: u64) -> Vec<u8> {
+    let mut input = Vec::new();
+    transactions_writer(Generator::new(config).take(rows as usize), None, &mut input).unwrap();
+    input
+}
+
+fn clients_state(transactions: &[Transaction]) -> HashMap<ClientId, ClientState> {
+    let mut engine = Engine::default();
+    for transaction in transactions {
+        engine.handle_transaction(transaction);
+    }
+    engine.clients_state
+}
+
+fn bench_csv_reader(c: &mut Criterion) {
+    let mut group = c.benchmark_group("csv_reader");
+    group.sample_size(10);
+    for (name, parameter, config, rows) in workloads() {
+        let input = csv_input(config, rows);
+        group.throughput(Throughput::Elements(rows));
+        group.bench_with_input(BenchmarkId::new(name, &parameter), &input, |b, input| b.iter(|| csv_reader(input.as_slice()).unwrap()));
+    }
+    group.finish();
+}
+
+/// Parsing alone, with the fast path of `csv_reader` and with serde.
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for (name, parameter, config, rows) in workloads() {
+        let input = csv_input(config, rows);
+        group.throughput(Throughput::Elements(rows));
+        group.bench_with_input(BenchmarkId::new(format!("fast/{name}"), &parameter), &input, |b, input| {
+            b.iter(|| {
+                let mut reader = TransactionReader::new(input.as_slice()).unwrap();
+                let mut transaction = Transaction::default();
+                while reader.read(&mut transaction).unwrap() {
+                    black_box(&transaction);
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new(format!("serde/{name}"), &parameter), &input, |b, input| {
+            b.iter(|| {
+                let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(input.as_slice());
+                for result in reader.deserialize::<Transaction>() {
+                    black_box(result.unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// `parallel_feed` on the rayon thread pool, to compare with `csv_reader`.
+fn bench_parallel_feed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_feed");
+    group.sample_size(10);
+    for (name, parameter, config, rows) in workloads() {
+        let input = csv_input(config, rows);
+        group.throughput(Throughput::Elements(rows));
+        group.bench_with_input(BenchmarkId::new(name, &parameter), &input, |b, input| {
+            b.iter(|| parallel_feed(input, &mut Engine::default(), DEFAULT_BLOCK_SIZE).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_handle_transaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handle_transaction");
+    group.sample_size(10);
+    for (name, parameter, config, rows) in workloads() {
+        let transactions = transactions(config, rows);
+        group.throughput(Throughput::Elements(rows));
+        group.bench_with_input(BenchmarkId::new(name, &parameter), &transactions, |b, transactions| b.iter(|| clients_state(transactions)));
+    }
+    group.finish();
+}
+
+fn bench_csv_writer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("csv_writer");
+    group.sample_size(10);
+    for (name, parameter, config, rows) in workloads() {
+        let clients_state = clients_state(&transactions(config, rows));
+        group.throughput(Throughput::Elements(clients_state.len() as u64));
+        group.bench_with_input(BenchmarkId::new(name, &parameter), &clients_state, |b, clients_state| {
+            b.iter_batched(
+                || clients_state.clone(),
+                |clients_state| csv_writer(clients_state, std::io::sink(), OutputOptions::default()).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_csv_reader, bench_parse, bench_parallel_feed, bench_handle_transaction, bench_csv_writer);
+criterion_main!(benches);
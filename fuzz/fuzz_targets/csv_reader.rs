@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payment_engine::io::csv_reader;
+
+// Malformed input must be reported as an error, never make the reader or the engine panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = csv_reader(data);
+});
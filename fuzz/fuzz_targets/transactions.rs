@@ -0,0 +1,50 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use payment_engine::engine::{Engine, EngineConfig, Transaction, TransactionType};
+
+const KINDS: [TransactionType; 8] = [
+    TransactionType::Deposit,
+    TransactionType::Withdrawal,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+    TransactionType::Refund,
+    TransactionType::Representment,
+    TransactionType::Arbitration,
+];
+
+/// Transaction as generated by the fuzzer, amounts being whole hundredths so that they stay finite and positive.
+#[derive(Arbitrary, Debug)]
+struct FuzzTransaction {
+    kind: u8,
+    client: u8,
+    tx: u8,
+    cents: Option<u16>,
+}
+
+impl From<&FuzzTransaction> for Transaction {
+    fn from(generated: &FuzzTransaction) -> Self {
+        Transaction {
+            tx_type: KINDS[generated.kind as usize % KINDS.len()],
+            client: u16::from(generated.client % 4),
+            tx: u32::from(generated.tx),
+            amount: generated.cents.map(|cents| f64::from(cents) / 100.0),
+            operator: None,
+            timestamp: None,
+            seq: None,
+            currency: None,
+        }
+    }
+}
+
+// Any sequence of transactions must keep the engine's invariants and a balanced ledger.
+fuzz_target!(|transactions: Vec<FuzzTransaction>| {
+    let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+    for transaction in &transactions {
+        engine.handle_transaction(&Transaction::from(transaction));
+    }
+    assert!(engine.violations.is_empty(), "{:?}", engine.violations);
+    assert!(engine.ledger.trial_balance().is_balanced());
+});
@@ -0,0 +1,25 @@
+use crate::engine::{ClientId, TransactionId};
+
+pub type OperatorId = u16;
+
+/// Administrative operation on a client's account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminAction {
+    /// Reactivates a locked or frozen account
+    Unlock,
+    /// Blocks withdrawals but allows deposits
+    Freeze,
+    /// Pays out available funds and retires the account
+    Close,
+}
+
+/// Represents an administrative operation applied by an operator, kept apart from client transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdminEntry {
+    pub action: AdminAction,
+    pub operator: OperatorId,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    /// Funds paid out to the client when closing the account
+    pub payout: f64,
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+
+use crate::engine::{ClientId, ClientState, DisputeState, Transaction, TransactionId, TransactionType};
+
+/// Maximum difference tolerated when comparing balances, amounts being floats.
+const TOLERANCE: f64 = 1e-9;
+
+/// Invariant the engine must keep after each transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Invariant {
+    /// Total must equal available plus held plus pending
+    Total { total: f64, sum: f64 },
+    /// Held must never be negative
+    NegativeHeld { held: f64 },
+    /// Held must equal the sum of the client's disputed transactions
+    Held { held: f64, disputed: f64 },
+    /// A locked account only changes through the chargeback cycle, clearing, admin operations
+    /// or operations the permission matrix allows
+    LockedAccountChanged,
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invariant::Total { total, sum } => {
+                write!(f, "total {total:.4} differs from available + held + pending {sum:.4}")
+            }
+            Invariant::NegativeHeld { held } => write!(f, "held {held:.4} is negative"),
+            Invariant::Held { held, disputed } => {
+                write!(f, "held {held:.4} differs from the disputed amount {disputed:.4}")
+            }
+            Invariant::LockedAccountChanged => write!(f, "locked account changed"),
+        }
+    }
+}
+
+/// Represents an invariant broken after handling a row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Position of the row among the transactions handled by the engine, starting at 1
+    pub row: usize,
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub invariant: Invariant,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {} ({} of client {}, tx {}) breaks an invariant: {}",
+            self.row, self.tx_type.as_str(), self.client, self.tx, self.invariant
+        )
+    }
+}
+
+/// State of the client and of the referenced transaction before a row is handled.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditSnapshot {
+    pub client_state: ClientState,
+    pub dispute_state: Option<DisputeState>,
+}
+
+/// Checks the client's state after a row against the invariants, `disputed` being the sum of its disputed transactions.
+///
+/// `exempt` tells whether the row may change a locked account.
+pub fn check(
+    before: &AuditSnapshot,
+    after: &ClientState,
+    disputed: f64,
+    exempt: bool,
+    transaction: &Transaction,
+    row: usize,
+) -> Vec<Violation> {
+    let mut invariants = Vec::new();
+    let sum = after.available + after.held + after.pending;
+    if (after.total - sum).abs() > TOLERANCE {
+        invariants.push(Invariant::Total { total: after.total, sum });
+    }
+    if after.held < -TOLERANCE {
+        invariants.push(Invariant::NegativeHeld { held: after.held });
+    }
+    if (after.held - disputed).abs() > TOLERANCE {
+        invariants.push(Invariant::Held { held: after.held, disputed });
+    }
+    let balances = |state: &ClientState| (state.available, state.held, state.total, state.pending);
+    if before.client_state.status.is_locked() && balances(&before.client_state) != balances(after) && !exempt {
+        invariants.push(Invariant::LockedAccountChanged);
+    }
+    invariants
+        .into_iter()
+        .map(|invariant| Violation {
+            row,
+            tx_type: transaction.tx_type,
+            client: transaction.client,
+            tx: transaction.tx,
+            invariant,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_reports_every_broken_invariant() {
+        let transaction = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(1.0),
+            operator: None,
+            timestamp: None,
+            seq: None,
+            currency: None,
+        };
+        let locked = ClientState { status: crate::status::AccountStatus::Locked, ..Default::default() };
+        let before = AuditSnapshot { client_state: locked, dispute_state: None };
+        let after = ClientState { available: 1.0, held: -1.0, total: 1.0, ..locked };
+
+        let violations = check(&before, &after, 0.0, false, &transaction, 3);
+        let invariants: Vec<Invariant> = violations.iter().map(|violation| violation.invariant).collect();
+        assert_eq!(
+            invariants,
+            vec![
+                Invariant::Total { total: 1.0, sum: 0.0 },
+                Invariant::NegativeHeld { held: -1.0 },
+                Invariant::Held { held: -1.0, disputed: 0.0 },
+                Invariant::LockedAccountChanged,
+            ]
+        );
+        assert_eq!(
+            violations[3].to_string(),
+            "row 3 (deposit of client 1, tx 1) breaks an invariant: locked account changed"
+        );
+    }
+}
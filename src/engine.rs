@@ -1,208 +1,1168 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::admin::{AdminAction, AdminEntry, OperatorId};
+use crate::audit::{self, AuditSnapshot, Violation};
+use crate::fees::{FeeEntry, FeeKind, FeeSchedule};
+use crate::history::TransactionHistory;
+use crate::interest::{InterestEntry, InterestPolicy};
+use crate::ledger::{Account, ClientBalance, Ledger, LedgerEntry};
+use crate::limits::{LimitAction, LimitBreach, Limits, VelocityWindow};
+use crate::status::{AccountStatus, Operation, PermissionMatrix};
 
 pub type TransactionId = u32;
 pub type ClientId = u16;
+/// Seconds since the Unix epoch
+pub type Timestamp = u64;
 
 /// Represents a transaction done by a client.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Transaction {
-    /// Type of transaction, one of (deposit, withdrawal, dispute, resolve, chargeback)
-    #[serde(rename(deserialize = "type"))]
-    tx_type: String,
-    client: ClientId,
-    tx: TransactionId,
+    /// Type of transaction, see `Engine::handle_transaction` for the recognized types
+    #[serde(rename(deserialize = "type"), deserialize_with = "deserialize_tx_type")]
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TransactionId,
     /// Can be None if tx_type is dispute, resolve or chargeback
-    amount: Option<f64>,
+    pub amount: Option<f64>,
+    /// Only set on admin transactions (unlock, freeze, close)
+    #[serde(default)]
+    pub operator: Option<OperatorId>,
+    /// Optional, either RFC 3339 or seconds since the Unix epoch
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub timestamp: Option<Timestamp>,
+    /// Optional sequence number set by the upstream feed
+    #[serde(default)]
+    pub seq: Option<u64>,
+    /// Optional currency the client paid or got paid in, the amount being already converted to the engine's currency
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// Type of a transaction, see `Engine::handle_transaction` for the recognized types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Refund,
+    Representment,
+    Arbitration,
+    Clear,
+    Return,
+    Unlock,
+    Freeze,
+    Close,
+    Eod,
+    /// Interest posted by the engine, not accepted in the input
+    Interest,
+    /// Type not recognized by the engine, reported when handled
+    #[default]
+    Unrecognized,
+}
+
+impl TransactionType {
+    /// Parses the value of the `type` column without allocating, None for unrecognized types.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            b"deposit" => Some(TransactionType::Deposit),
+            b"withdrawal" => Some(TransactionType::Withdrawal),
+            b"dispute" => Some(TransactionType::Dispute),
+            b"resolve" => Some(TransactionType::Resolve),
+            b"chargeback" => Some(TransactionType::Chargeback),
+            b"refund" => Some(TransactionType::Refund),
+            b"representment" => Some(TransactionType::Representment),
+            b"arbitration" => Some(TransactionType::Arbitration),
+            b"clear" => Some(TransactionType::Clear),
+            b"return" => Some(TransactionType::Return),
+            b"unlock" => Some(TransactionType::Unlock),
+            b"freeze" => Some(TransactionType::Freeze),
+            b"close" => Some(TransactionType::Close),
+            b"eod" => Some(TransactionType::Eod),
+            _ => None,
+        }
+    }
+
+    /// Value of the `type` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Refund => "refund",
+            TransactionType::Representment => "representment",
+            TransactionType::Arbitration => "arbitration",
+            TransactionType::Clear => "clear",
+            TransactionType::Return => "return",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Freeze => "freeze",
+            TransactionType::Close => "close",
+            TransactionType::Eod => "eod",
+            TransactionType::Interest => "interest",
+            TransactionType::Unrecognized => "unrecognized",
+        }
+    }
+}
+
+fn deserialize_tx_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TransactionType, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Ok(TransactionType::from_bytes(value.as_bytes()).unwrap_or_default())
+}
+
+/// Parses a timestamp given either as RFC 3339 or as seconds since the Unix epoch.
+pub fn parse_timestamp(value: &str) -> Result<Timestamp, String> {
+    if let Ok(seconds) = value.parse() {
+        return Ok(seconds);
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .and_then(|datetime| Timestamp::try_from(datetime.timestamp()).ok())
+        .ok_or_else(|| format!("invalid timestamp {value}, expected RFC 3339 or seconds since the Unix epoch"))
+}
+
+/// Timestamp as found in the input: a number of seconds in JSON, text otherwise.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Seconds(Timestamp),
+    Text(String),
+}
+
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Timestamp>, D::Error> {
+    match Option::<RawTimestamp>::deserialize(deserializer)? {
+        Some(RawTimestamp::Seconds(seconds)) => Ok(Some(seconds)),
+        Some(RawTimestamp::Text(value)) if !value.is_empty() => parse_timestamp(&value).map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
 }
 
 impl Transaction {
     fn get_amount(&self) -> f64 {
         self.amount.unwrap_or_default()
     }
+
+    /// Tells whether the amount moves funds, deposits, withdrawals and refunds being ignored otherwise.
+    fn has_positive_amount(&self) -> bool {
+        self.get_amount() > 0.0
+    }
+
+    /// Tells whether the transaction was made in another currency than `currency`, the engine's.
+    fn is_conversion(&self, currency: Option<&str>) -> bool {
+        self.currency.as_deref().is_some_and(|code| Some(code) != currency)
+    }
+
+    /// Tells whether the transaction references an earlier transaction of the client through its tx id.
+    pub fn references_history(&self) -> bool {
+        matches!(
+            self.tx_type,
+            TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Refund
+                | TransactionType::Representment
+                | TransactionType::Arbitration
+                | TransactionType::Clear
+                | TransactionType::Return
+        )
+    }
+
+    /// Key used to restore the input order: the sequence number if any, the timestamp otherwise.
+    pub fn order_key(&self) -> Option<u64> {
+        self.seq.or(self.timestamp)
+    }
 }
 
 /// Represents the final state of a client after handling all of his transaction_history.
-#[derive(Deserialize, Debug, PartialEq, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
 pub struct ClientState {
     pub available: f64,
     pub held: f64,
     pub total: f64,
-    pub locked: bool,
+    pub status: AccountStatus,
+    /// Fees collected from the client, already deducted from available and total
+    pub fees: f64,
+    /// Deposited funds not cleared yet, counted in total but not in available
+    pub pending: f64,
+    /// How far available went below zero, e.g. by drawing on the credit line
+    pub overdrawn: f64,
+}
+
+impl ClientState {
+    fn refresh_overdrawn(&mut self) {
+        self.overdrawn = (-self.available).max(0.0);
+    }
+
+    fn balance_mut(&mut self, balance: ClientBalance) -> &mut f64 {
+        match balance {
+            ClientBalance::Available => &mut self.available,
+            ClientBalance::Held => &mut self.held,
+            ClientBalance::Pending => &mut self.pending,
+        }
+    }
+
+    /// Applies a ledger entry to the client's balances, which only ever change this way.
+    /// 
+    /// Total moves when funds enter or leave the client's accounts, not when they move between them.
+    fn apply(&mut self, entry: &LedgerEntry) {
+        if let Account::Client(_, balance) = entry.debit {
+            *self.balance_mut(balance) -= entry.amount;
+        }
+        if let Account::Client(_, balance) = entry.credit {
+            *self.balance_mut(balance) += entry.amount;
+        }
+        match (entry.debit, entry.credit) {
+            (Account::Client(..), Account::Client(..)) => {}
+            (Account::Client(..), _) => self.total -= entry.amount,
+            (_, Account::Client(..)) => self.total += entry.amount,
+            _ => {}
+        }
+    }
+}
+
+/// Represents a change of a client's state, caused by a transaction or by the engine itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub client: ClientId,
+    pub tx: TransactionId,
+    /// Type of the transaction, or "clear", "resolve", "chargeback", "interest" for changes made by the engine
+    pub kind: String,
+    pub timestamp: Option<Timestamp>,
+    pub before: ClientState,
+    pub after: ClientState,
+}
+
+/// Kind of a historized transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Dispute state of a historized transaction.
+/// 
+/// Legal transitions are:
+/// * `Undisputed` -> `Disputed` (dispute)
+/// * `Disputed` -> `Undisputed` (resolve)
+/// * `Disputed` -> `ChargedBack` (chargeback)
+/// * `ChargedBack` -> `Represented` (representment)
+/// * `Represented` -> `PreArbitration` (chargeback)
+/// * `PreArbitration` -> `Arbitrated` (arbitration)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisputeState {
+    Undisputed,
+    Disputed,
+    /// Funds have been charged back
+    ChargedBack,
+    /// The chargeback has been reversed by a second presentment, funds are re-credited
+    Represented,
+    /// The representment has been charged back again, funds are debited until arbitration
+    PreArbitration,
+    /// The second chargeback has been reversed by arbitration, funds are re-credited for good
+    Arbitrated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TransactionSummary {
+    pub kind: TransactionKind,
+    /// Remaining disputable amount, shrinks when the transaction is refunded
     pub amount: f64,
-    pub is_disputed: bool,
+    pub dispute_state: DisputeState,
+    /// Time of the transaction, if the input provides one
+    pub timestamp: Option<Timestamp>,
+    /// Time of the latest dispute, if the dispute provides one
+    pub disputed_at: Option<Timestamp>,
+    pub clearing_state: ClearingState,
 }
 
-/// Handles deposit transaction by updating client's state and adding current transaction to history.
-/// 
-/// Increases available and total.
-fn handle_deposit(
-    transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
-) {
-    // We historize the transaction in order to deal with disputes, resolves, and chargebacks later.
-    transaction_history.insert(
-        (transaction.client, transaction.tx),
-        TransactionSummary {
-            amount: transaction.get_amount(),
-            is_disputed: false,
-        },
-    );
-    clients_state
-        .entry(transaction.client)
-        .and_modify(|client_state| {
-            if !client_state.locked {
-                client_state.available += transaction.get_amount();
-                client_state.total += transaction.get_amount();
-            }
-        })
-        .or_insert(ClientState {
-            available: transaction.get_amount(),
-            held: 0.0,
-            total: transaction.get_amount(),
-            locked: false,
-        });
+/// Clearing state of a historized transaction, only deposits can be pending.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearingState {
+    /// Funds are counted in total but not available yet
+    Pending,
+    Cleared,
+    /// The deposit was reversed before clearing
+    Returned,
 }
 
-/// Handles withdrawal transaction by updating client's state and adding current transaction to history.
-/// 
-/// Decreases available and total.
-fn handle_withdrawal(
-    transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
-) {
-    // We historize the transaction in order to deal with disputes, resolves, and chargebacks later.
-    transaction_history.insert(
-        (transaction.client, transaction.tx),
-        TransactionSummary {
-            amount: transaction.get_amount(),
-            is_disputed: false,
-        },
-    );
-    clients_state
-        .entry(transaction.client)
-        .and_modify(|client_state| {
-            if !client_state.locked
-                && client_state.available >= transaction.get_amount()
-            {
-                client_state.available -= transaction.get_amount();
-                client_state.total -= transaction.get_amount();
-            }
-        })
-        .or_default(); // Create a new record
+/// What happens to a dispute still open at its resolution deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DeadlinePolicy {
+    #[default]
+    Resolve,
+    Chargeback,
 }
 
-/// Handles dispute transaction by updating client's state.
-/// 
-/// Decreases available, increases held and flags transaction as disputed.
-fn handle_dispute(
+/// Represents a line of the credit limits configuration, granting a credit line to a client.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CreditLimit {
+    pub client: ClientId,
+    pub credit_limit: f64,
+}
+
+/// Settings of the engine, fixed for a whole run.
+#[derive(Debug, Default, Clone)]
+pub struct EngineConfig {
+    pub fee_schedule: FeeSchedule,
+    /// Currency balances are kept in, deposits and withdrawals made in another currency are conversions
+    pub currency: Option<String>,
+    /// Operations accepted for each account status
+    pub permissions: PermissionMatrix,
+    /// Approved overdraft per client, letting available go negative down to minus that amount
+    pub credit_limits: HashMap<ClientId, f64>,
+    /// Limits on deposits and withdrawals per client or tier
+    pub limits: Limits,
+    /// Unlocks the account when a chargeback is reversed by a representment or an arbitration
+    pub unlock_on_reversal: bool,
+    /// Deposits are pending until cleared by a clear row or by the clearing delay
+    pub pending_deposits: bool,
+    /// Time in seconds after which a pending deposit is cleared
+    pub clearing_delay: Option<Timestamp>,
+    /// Maximum age in seconds of a transaction when it gets disputed
+    pub dispute_window: Option<Timestamp>,
+    /// Time in seconds after which an open dispute is settled according to `deadline_policy`
+    pub dispute_deadline: Option<Timestamp>,
+    pub deadline_policy: DeadlinePolicy,
+    /// Interest accrued on available balances at the end of each business day
+    pub interest: Option<InterestPolicy>,
+    /// Records every change of a client's state in the engine's journal
+    pub journal: bool,
+    /// Keeps every ledger entry, balances are kept either way
+    pub ledger: bool,
+    /// Checks the invariants after each transaction and records the violations, always on in unit tests where violations panic
+    pub audit: bool,
+}
+
+/// Holds the state of all clients along with the history needed to handle disputes.
+#[derive(Debug, Default)]
+pub struct Engine {
+    pub config: EngineConfig,
+    /// History of all previous transactions, identified by client id and transaction id respectively
+    pub transaction_history: TransactionHistory,
+    /// The current state of all clients, identified by client id
+    pub clients_state: HashMap<ClientId, ClientState>,
+    /// Client ids in the order the clients were first seen
+    pub clients_order: Vec<ClientId>,
+    /// Fees charged to each client, identified by client id
+    pub fee_history: HashMap<ClientId, Vec<FeeEntry>>,
+    /// Admin operations applied so far, in order
+    pub admin_history: Vec<AdminEntry>,
+    /// Recent deposits and withdrawals per client, needed by windowed limits
+    pub velocity: HashMap<(ClientId, Operation), VelocityWindow>,
+    /// Transactions that breached a limit, in order
+    pub limit_breaches: Vec<LimitBreach>,
+    /// Timestamp of the latest transaction of each client, identified by client id
+    pub last_timestamps: HashMap<ClientId, Timestamp>,
+    /// Interest posted to each client, identified by client id
+    pub interest_history: HashMap<ClientId, Vec<InterestEntry>>,
+    /// Changes of the clients' state not consumed yet, in order, only recorded if the config enables it
+    pub journal: Vec<JournalEntry>,
+    /// General ledger every balance movement is posted to
+    pub ledger: Ledger,
+    /// Invariants broken so far, only checked in audit mode
+    pub violations: Vec<Violation>,
+    /// Number of transactions handled so far
+    rows: usize,
+    /// Disputed transactions of each client as of its last audited row, in order to sum the disputed amounts in audit mode.
+    /// A transaction only becomes disputed through a row referencing it
+    audited_txs: HashMap<ClientId, HashSet<TransactionId>>,
+    /// Business day currently open, known once a timestamp has been seen
+    business_day: Option<u64>,
+    /// Number of system transaction ids issued so far, allocated downwards from the highest id
+    system_txs: TransactionId,
+    /// Deadlines of the open disputes, identified by client id and transaction id respectively
+    open_disputes: BinaryHeap<Reverse<(Timestamp, ClientId, TransactionId)>>,
+    /// Clearing times of the pending deposits, identified by client id and transaction id respectively
+    pending_deposits: BinaryHeap<Reverse<(Timestamp, ClientId, TransactionId)>>,
+}
+
+/// Posts an entry to the ledger and applies it to the client's balances.
+fn post(ledger: &mut Ledger, client_state: &mut ClientState, entry: LedgerEntry) {
+    client_state.apply(&entry);
+    ledger.post(entry);
+}
+
+/// Charges a fee on a transaction to the client and records it in the client's fee history.
+fn charge_fee(
+    ledger: &mut Ledger,
+    client_state: &mut ClientState,
+    fee_history: &mut Vec<FeeEntry>,
     transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
+    fee: FeeEntry,
 ) {
-    clients_state
-        .entry(transaction.client)
-        .and_modify(|client_state| {
-            // By design, we ensure that the referenced transaction belongs to the client
-            // which prevents a client from disputing another client's transaction.
-            if let Some(referenced_transaction) =
-                transaction_history.get_mut(&(transaction.client, transaction.tx))
-            {
-                if !client_state.locked {
-                    client_state.available -= referenced_transaction.amount;
-                    client_state.held += referenced_transaction.amount;
-                    referenced_transaction.is_disputed = true;
-                }
-            }
-        })
-        .or_default();
+    if fee.amount > 0.0 {
+        let available = Account::Client(transaction.client, ClientBalance::Available);
+        let entry = LedgerEntry {
+            kind: "fee".to_string(),
+            ..LedgerEntry::new(transaction, available, Account::FeeIncome, fee.amount)
+        };
+        post(ledger, client_state, entry);
+        client_state.fees += fee.amount;
+        fee_history.push(fee);
+    }
 }
 
-/// Handles withdrawal transaction by updating client's state
-/// 
-/// Decreases held, increases available and flags transaction as no longer disputed.
-fn handle_resolve(
+/// Records an applied transaction for windowed limits.
+fn record_velocity(
+    velocity: &mut HashMap<(ClientId, Operation), VelocityWindow>,
+    limits: &Limits,
     transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
+    operation: Operation,
 ) {
-    clients_state
-        .entry(transaction.client)
-        .and_modify(|client_state| {
-            // By design, we ensure that the referenced transaction belongs to the client
-            // which prevents a client from disputing another client's transaction.
-            if let Some(referenced_transaction) =
-                transaction_history.get_mut(&(transaction.client, transaction.tx))
-            {
-                if !client_state.locked && referenced_transaction.is_disputed {
-                    client_state.held -= referenced_transaction.amount;
-                    client_state.available += referenced_transaction.amount;
-                    referenced_transaction.is_disputed = false;
-                }
-            }
-        })
-        .or_default();
+    if let Some(timestamp) = transaction.timestamp {
+        if !limits.is_empty() {
+            velocity
+                .entry((transaction.client, operation))
+                .or_default()
+                .record(timestamp, transaction.get_amount(), limits.longest_window());
+        }
+    }
 }
 
-/// Handles withdrawal transaction by updating client's state
-/// 
-/// Decreases held and total, and flags transaction as no longer disputed.
-/// 
-/// Also flags the client's state as locked.
-fn handle_chargeback(
-    transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
-) { 
-    clients_state
-        .entry(transaction.client)
-        .and_modify(|client_state| {
-            // By design, we ensure that the referenced transaction belongs to the client
-            // which prevents a client from disputing another client's transaction.
-            if let Some(referenced_transaction) =
-                transaction_history.get_mut(&(transaction.client, transaction.tx))
+impl Engine {
+    pub fn new(config: EngineConfig) -> Self {
+        Engine {
+            ledger: Ledger::new(config.ledger),
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// State of each client, in the order the clients were first seen.
+    pub fn clients_by_first_seen(&self) -> impl Iterator<Item = (ClientId, ClientState)> + '_ {
+        self.clients_order.iter().map(|client| (*client, self.clients_state[client]))
+    }
+
+    /// State of the client before a change, only taken if the journal is enabled.
+    fn snapshot(&self, client: ClientId) -> Option<ClientState> {
+        self.config
+            .journal
+            .then(|| self.clients_state.get(&client).copied().unwrap_or_default())
+    }
+
+    /// Records the change of the client's state since the snapshot, if any.
+    fn record(&mut self, transaction: &Transaction, before: Option<ClientState>) {
+        let Some(before) = before else {
+            return;
+        };
+        let after = self.clients_state.get(&transaction.client).copied().unwrap_or_default();
+        if after != before {
+            self.journal.push(JournalEntry {
+                client: transaction.client,
+                tx: transaction.tx,
+                kind: transaction.tx_type.as_str().to_string(),
+                timestamp: transaction.timestamp,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Checks the invariants on the client of the transaction just handled, and reports every violation.
+    fn audit(&mut self, transaction: &Transaction, before: &AuditSnapshot) {
+        let client = transaction.client;
+        let history = &self.transaction_history;
+        let audited_txs = self.audited_txs.entry(client).or_default();
+        audited_txs.insert(transaction.tx);
+        audited_txs.retain(|tx| {
+            history
+                .get(&(client, *tx))
+                .is_some_and(|summary| summary.dispute_state == DisputeState::Disputed)
+        });
+        let disputed = audited_txs.iter().filter_map(|tx| history.get(&(client, *tx))).map(|summary| summary.amount).sum();
+        let exempt = match transaction.tx_type {
+            TransactionType::Representment
+            | TransactionType::Arbitration
+            | TransactionType::Clear
+            | TransactionType::Return
+            | TransactionType::Unlock
+            | TransactionType::Freeze
+            | TransactionType::Close => true,
+            TransactionType::Chargeback if before.dispute_state == Some(DisputeState::Represented) => true,
+            tx_type => Operation::from_tx_type(tx_type)
+                .is_some_and(|operation| self.config.permissions.allows(before.client_state.status, operation)),
+        };
+        let after = self.clients_state.get(&client).copied().unwrap_or_default();
+        for violation in audit::check(before, &after, disputed, exempt, transaction, self.rows) {
+            eprintln!("Error: {violation}");
+            self.violations.push(violation);
+        }
+    }
+
+    /// Status of the client's account, active for unknown clients.
+    fn status_of(&self, client: ClientId) -> AccountStatus {
+        self.clients_state.get(&client).map(|client_state| client_state.status).unwrap_or_default()
+    }
+
+    /// Checks the transaction against the client's limits and records any breach.
+    /// 
+    /// Returns false if the transaction must be rejected.
+    fn check_limits(&mut self, transaction: &Transaction, operation: Operation) -> bool {
+        let breaches = self.config.limits.check(
+            transaction.client,
+            operation,
+            transaction.get_amount(),
+            transaction.timestamp,
+            self.velocity.get(&(transaction.client, operation)).unwrap_or(&VelocityWindow::default()),
+        );
+        let mut accepted = true;
+        for (reason, action) in breaches {
+            accepted &= action != LimitAction::Reject;
+            self.limit_breaches.push(LimitBreach {
+                client: transaction.client,
+                tx: transaction.tx,
+                operation,
+                reason,
+                action,
+            });
+        }
+        accepted
+    }
+
+    /// Handles deposit transaction by updating client's state and adding current transaction to history.
+    /// 
+    /// Increases available and total, or pending and total if deposits must clear first.
+    /// 
+    /// The deposit is ignored if its amount is not positive, if it reuses a transaction id of the client,
+    /// if the account status doesn't allow it, or if it is rejected by the client's limits.
+    /// Limits are only checked once the status allows the deposit, so that a denied deposit doesn't count as a breach.
+    /// 
+    /// A deposit made in another currency is charged the conversion fee if any, from available.
+    fn handle_deposit(&mut self, transaction: &Transaction) {
+        if !transaction.has_positive_amount() || self.transaction_history.contains_key(&(transaction.client, transaction.tx)) {
+            return;
+        }
+        let allowed = self.config.permissions.allows(self.status_of(transaction.client), Operation::Deposit);
+        if allowed && !self.check_limits(transaction, Operation::Deposit) {
+            return;
+        }
+        let conversion_fee = self.conversion_fee(transaction);
+        let client_state = self.clients_state.entry(transaction.client).or_default();
+        if !allowed {
+            return;
+        }
+        let (clearing_state, balance) = if self.config.pending_deposits {
+            if let (Some(delay), Some(now)) = (self.config.clearing_delay, transaction.timestamp) {
+                self.pending_deposits.push(Reverse((now.saturating_add(delay), transaction.client, transaction.tx)));
+            }
+            (ClearingState::Pending, ClientBalance::Pending)
+        } else {
+            (ClearingState::Cleared, ClientBalance::Available)
+        };
+        let credited = Account::Client(transaction.client, balance);
+        post(
+            &mut self.ledger,
+            client_state,
+            LedgerEntry::new(transaction, Account::BankCash, credited, transaction.get_amount()),
+        );
+        if conversion_fee > 0.0 {
+            charge_fee(
+                &mut self.ledger,
+                client_state,
+                self.fee_history.entry(transaction.client).or_default(),
+                transaction,
+                FeeEntry { kind: FeeKind::Conversion, tx: transaction.tx, amount: conversion_fee },
+            );
+        }
+        record_velocity(&mut self.velocity, &self.config.limits, transaction, Operation::Deposit);
+        // We historize the transaction in order to deal with disputes, resolves, and chargebacks later.
+        self.transaction_history.insert(
+            (transaction.client, transaction.tx),
+            TransactionSummary {
+                kind: TransactionKind::Deposit,
+                amount: transaction.get_amount(),
+                dispute_state: DisputeState::Undisputed,
+                timestamp: transaction.timestamp,
+                disputed_at: None,
+                clearing_state,
+            },
+        );
+    }
+
+    /// Fee charged on a deposit or withdrawal made in another currency than the engine's, 0 otherwise.
+    fn conversion_fee(&self, transaction: &Transaction) -> f64 {
+        if transaction.is_conversion(self.config.currency.as_deref()) {
+            self.config.fee_schedule.fee_for(FeeKind::Conversion, transaction.get_amount())
+        } else {
+            0.0
+        }
+    }
+
+    /// Handles withdrawal transaction by updating client's state and adding current transaction to history.
+    /// 
+    /// Decreases available and total, and charges the withdrawal fee if any.
+    /// When the withdrawal draws on the client's credit line, the overdraft fee is charged on the overdrawn part.
+    /// A withdrawal made in another currency is also charged the conversion fee.
+    /// 
+    /// The withdrawal is ignored if available funds and credit line can't cover the amount and the fees,
+    /// if its amount is not positive, if it reuses a transaction id of the client, if the account status doesn't allow it,
+    /// or if it is rejected by the client's limits. Limits are only checked once the status allows the withdrawal.
+    fn handle_withdrawal(&mut self, transaction: &Transaction) {
+        if !transaction.has_positive_amount() || self.transaction_history.contains_key(&(transaction.client, transaction.tx)) {
+            return;
+        }
+        let allowed = self.config.permissions.allows(self.status_of(transaction.client), Operation::Withdrawal);
+        if allowed && !self.check_limits(transaction, Operation::Withdrawal) {
+            return;
+        }
+        // We historize the transaction in order to deal with disputes, resolves, and chargebacks later.
+        self.transaction_history.insert(
+            (transaction.client, transaction.tx),
+            TransactionSummary {
+                kind: TransactionKind::Withdrawal,
+                amount: transaction.get_amount(),
+                dispute_state: DisputeState::Undisputed,
+                timestamp: transaction.timestamp,
+                disputed_at: None,
+                clearing_state: ClearingState::Cleared,
+            },
+        );
+        let fee = self.config.fee_schedule.fee_for(FeeKind::Withdrawal, transaction.get_amount());
+        let conversion_fee = self.conversion_fee(transaction);
+        let credit_limit = self.config.credit_limits.get(&transaction.client).copied().unwrap_or_default();
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                let drawn = (transaction.get_amount() + fee + conversion_fee - client_state.available.max(0.0)).max(0.0);
+                let overdraft_fee = if drawn > 0.0 {
+                    self.config.fee_schedule.fee_for(FeeKind::Overdraft, drawn)
+                } else {
+                    0.0
+                };
+                if self.config.permissions.allows(client_state.status, Operation::Withdrawal)
+                    && client_state.available + credit_limit >= transaction.get_amount() + fee + conversion_fee + overdraft_fee
+                {
+                    let available = Account::Client(transaction.client, ClientBalance::Available);
+                    post(
+                        &mut self.ledger,
+                        client_state,
+                        LedgerEntry::new(transaction, available, Account::BankCash, transaction.get_amount()),
+                    );
+                    let fee_history = self.fee_history.entry(transaction.client).or_default();
+                    charge_fee(
+                        &mut self.ledger,
+                        client_state,
+                        fee_history,
+                        transaction,
+                        FeeEntry { kind: FeeKind::Withdrawal, tx: transaction.tx, amount: fee },
+                    );
+                    charge_fee(
+                        &mut self.ledger,
+                        client_state,
+                        fee_history,
+                        transaction,
+                        FeeEntry { kind: FeeKind::Conversion, tx: transaction.tx, amount: conversion_fee },
+                    );
+                    charge_fee(
+                        &mut self.ledger,
+                        client_state,
+                        fee_history,
+                        transaction,
+                        FeeEntry { kind: FeeKind::Overdraft, tx: transaction.tx, amount: overdraft_fee },
+                    );
+                    record_velocity(&mut self.velocity, &self.config.limits, transaction, Operation::Withdrawal);
+                }
+            })
+            .or_default(); // Create a new record
+    }
+
+    /// Handles dispute transaction by updating client's state.
+    /// 
+    /// Decreases available, increases held and flags transaction as disputed.
+    /// 
+    /// The dispute is ignored if the referenced transaction is not cleared yet or is older than the dispute window.
+    fn handle_dispute(&mut self, transaction: &Transaction) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from disputing another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    let too_old = match (self.config.dispute_window, referenced_transaction.timestamp, transaction.timestamp) {
+                        (Some(window), Some(transaction_time), Some(now)) => now.saturating_sub(transaction_time) > window,
+                        _ => false,
+                    };
+                    if self.config.permissions.allows(client_state.status, Operation::Dispute)
+                        && referenced_transaction.dispute_state == DisputeState::Undisputed
+                        && referenced_transaction.clearing_state == ClearingState::Cleared
+                        && !too_old
+                    {
+                        let (available, held) = (Account::Client(transaction.client, ClientBalance::Available), Account::Client(transaction.client, ClientBalance::Held));
+                        post(
+                            &mut self.ledger,
+                            client_state,
+                            LedgerEntry::new(transaction, available, held, referenced_transaction.amount),
+                        );
+                        referenced_transaction.dispute_state = DisputeState::Disputed;
+                        referenced_transaction.disputed_at = transaction.timestamp;
+                        if let (Some(deadline), Some(now)) = (self.config.dispute_deadline, transaction.timestamp) {
+                            self.open_disputes.push(Reverse((now.saturating_add(deadline), transaction.client, transaction.tx)));
+                        }
+                    }
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles resolve transaction by updating client's state
+    /// 
+    /// Decreases held, increases available and flags transaction as no longer disputed.
+    /// 
+    /// `by_deadline` - settled at the dispute deadline, which applies whatever the account status
+    fn handle_resolve(&mut self, transaction: &Transaction, by_deadline: bool) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from disputing another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    if (by_deadline || self.config.permissions.allows(client_state.status, Operation::Resolve))
+                        && referenced_transaction.dispute_state == DisputeState::Disputed
+                    {
+                        let (available, held) = (Account::Client(transaction.client, ClientBalance::Available), Account::Client(transaction.client, ClientBalance::Held));
+                        post(
+                            &mut self.ledger,
+                            client_state,
+                            LedgerEntry::new(transaction, held, available, referenced_transaction.amount),
+                        );
+                        referenced_transaction.dispute_state = DisputeState::Undisputed;
+                    }
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles chargeback transaction by updating client's state
+    /// 
+    /// On a disputed transaction, decreases held and total, and flags transaction as charged back.
+    /// 
+    /// On a represented transaction (second chargeback), decreases available and total,
+    /// and flags transaction as pending arbitration, unless the account is closed.
+    /// 
+    /// Also locks the client's account and charges the chargeback fee if any.
+    /// 
+    /// `by_deadline` - settled at the dispute deadline, which applies whatever the account status
+    fn handle_chargeback(&mut self, transaction: &Transaction, by_deadline: bool) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from disputing another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    let debited = match referenced_transaction.dispute_state {
+                        DisputeState::Disputed
+                            if by_deadline || self.config.permissions.allows(client_state.status, Operation::Chargeback) =>
+                        {
+                            referenced_transaction.dispute_state = DisputeState::ChargedBack;
+                            Account::Client(transaction.client, ClientBalance::Held)
+                        }
+                        DisputeState::Represented if client_state.status != AccountStatus::Closed => {
+                            referenced_transaction.dispute_state = DisputeState::PreArbitration;
+                            Account::Client(transaction.client, ClientBalance::Available)
+                        }
+                        _ => return,
+                    };
+                    post(
+                        &mut self.ledger,
+                        client_state,
+                        LedgerEntry::new(transaction, debited, Account::Chargebacks, referenced_transaction.amount),
+                    );
+                    client_state.status = AccountStatus::Locked;
+                    charge_fee(
+                        &mut self.ledger,
+                        client_state,
+                        self.fee_history.entry(transaction.client).or_default(),
+                        transaction,
+                        FeeEntry {
+                            kind: FeeKind::Chargeback,
+                            tx: transaction.tx,
+                            amount: self.config.fee_schedule.fee_for(FeeKind::Chargeback, referenced_transaction.amount),
+                        },
+                    );
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles representment and arbitration transactions by updating client's state
+    /// 
+    /// Reverses a chargeback (representment) or a second chargeback (arbitration):
+    /// increases available and total, and moves transaction to the next dispute state.
+    /// 
+    /// The reversal is ignored if the account is closed. A locked account is unlocked if `unlock_on_reversal` is set,
+    /// fees already charged are kept.
+    fn handle_chargeback_reversal(&mut self, transaction: &Transaction, from: DisputeState, to: DisputeState) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from disputing another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    if referenced_transaction.dispute_state == from && client_state.status != AccountStatus::Closed {
+                        let available = Account::Client(transaction.client, ClientBalance::Available);
+                        post(
+                            &mut self.ledger,
+                            client_state,
+                            LedgerEntry::new(transaction, Account::Chargebacks, available, referenced_transaction.amount),
+                        );
+                        referenced_transaction.dispute_state = to;
+                        if self.config.unlock_on_reversal && client_state.status == AccountStatus::Locked {
+                            client_state.status = AccountStatus::Active;
+                        }
+                    }
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles refund transaction by updating client's state.
+    /// 
+    /// The refund references an earlier deposit and returns part or all of it:
+    /// decreases available and total, and shrinks the remaining disputable amount of the deposit.
+    /// 
+    /// The refund is ignored if its amount is not positive, if the deposit is not cleared, if it is disputed
+    /// or if the refunded total would exceed the original amount.
+    fn handle_refund(&mut self, transaction: &Transaction) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from refunding another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    if self.config.permissions.allows(client_state.status, Operation::Refund)
+                        && transaction.has_positive_amount()
+                        && referenced_transaction.kind == TransactionKind::Deposit
+                        && referenced_transaction.dispute_state == DisputeState::Undisputed
+                        && referenced_transaction.clearing_state == ClearingState::Cleared
+                        && referenced_transaction.amount >= transaction.get_amount()
+                        && client_state.available >= transaction.get_amount()
+                    {
+                        let available = Account::Client(transaction.client, ClientBalance::Available);
+                        post(
+                            &mut self.ledger,
+                            client_state,
+                            LedgerEntry::new(transaction, available, Account::BankCash, transaction.get_amount()),
+                        );
+                        referenced_transaction.amount -= transaction.get_amount();
+                    }
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles clear and return transactions on a pending deposit, whatever the account status.
+    /// 
+    /// * clear - decreases pending and increases available
+    /// * return - decreases pending and total, the deposit is reversed
+    fn handle_clearing(&mut self, transaction: &Transaction, to: ClearingState) {
+        self.clients_state
+            .entry(transaction.client)
+            .and_modify(|client_state| {
+                // By design, we ensure that the referenced transaction belongs to the client
+                // which prevents a client from clearing another client's transaction.
+                if let Some(mut referenced_transaction) =
+                    self.transaction_history.get_mut(&(transaction.client, transaction.tx))
+                {
+                    if referenced_transaction.clearing_state == ClearingState::Pending {
+                        let credited = match to {
+                            ClearingState::Returned => Account::BankCash,
+                            _ => Account::Client(transaction.client, ClientBalance::Available),
+                        };
+                        let pending = Account::Client(transaction.client, ClientBalance::Pending);
+                        post(
+                            &mut self.ledger,
+                            client_state,
+                            LedgerEntry::new(transaction, pending, credited, referenced_transaction.amount),
+                        );
+                        referenced_transaction.clearing_state = to;
+                    }
+                }
+            })
+            .or_default();
+    }
+
+    /// Handles admin transaction by updating client's account status and recording it in the admin history.
+    /// 
+    /// * unlock - reactivates a locked or frozen account
+    /// * freeze - blocks withdrawals on an active account
+    /// * close - pays out available funds and retires the account, refused while funds are held or balance is negative
+    /// 
+    /// The transaction is ignored if it carries no operator id, if the client is unknown or if the account is closed.
+    fn handle_admin(&mut self, transaction: &Transaction, action: AdminAction) {
+        let Some(operator) = transaction.operator else {
+            eprintln!("Error: missing operator id on admin transaction {}", transaction.tx);
+            return;
+        };
+        let Some(client_state) = self.clients_state.get_mut(&transaction.client) else {
+            return;
+        };
+        let mut payout = 0.0;
+        match (action, client_state.status) {
+            (AdminAction::Unlock, AccountStatus::Locked | AccountStatus::Frozen) => {
+                client_state.status = AccountStatus::Active;
+            }
+            (AdminAction::Freeze, AccountStatus::Active) => client_state.status = AccountStatus::Frozen,
+            (AdminAction::Close, AccountStatus::Active | AccountStatus::Frozen | AccountStatus::Locked)
+                if client_state.held == 0.0 && client_state.available >= 0.0 =>
             {
-                if !client_state.locked && referenced_transaction.is_disputed {
-                    client_state.held -= referenced_transaction.amount;
-                    client_state.total -= referenced_transaction.amount;
-                    referenced_transaction.is_disputed = false;
-                    client_state.locked = true;
+                payout = client_state.available;
+                if payout > 0.0 {
+                    let available = Account::Client(transaction.client, ClientBalance::Available);
+                    post(&mut self.ledger, client_state, LedgerEntry::new(transaction, available, Account::BankCash, payout));
                 }
+                client_state.status = AccountStatus::Closed;
             }
-        })
-        .or_default();
-}
+            _ => return,
+        }
+        self.admin_history.push(AdminEntry {
+            action,
+            operator,
+            client: transaction.client,
+            tx: transaction.tx,
+            payout,
+        });
+    }
 
-/// Dispatches receiving transaction to the correct handler.
-/// 
-/// Transaction type must be one of "deposit", "withdrawal", "dispute", "resolve", or "chargeback"
-/// 
-/// There will be no update if the client's account is locked.
-/// 
-/// # Arguments
-/// 
-/// * `transaction` - the current transaction
-/// * `transaction_history` - history of all previous transactions, identified by client id and transaction id respectively
-/// * `clients_state` - the current state of all clients, identified by client id
-pub fn handle_transaction(
-    transaction: &Transaction,
-    transaction_history: &mut HashMap<(ClientId, TransactionId), TransactionSummary>,
-    clients_state: &mut HashMap<ClientId, ClientState>
-) {
-    match transaction.tx_type.as_str() {
-        "deposit" => handle_deposit(transaction, transaction_history, clients_state),
-        "withdrawal" => handle_withdrawal(transaction, transaction_history, clients_state),
-        "dispute" => handle_dispute(transaction, transaction_history, clients_state),
-        "resolve" => handle_resolve(transaction, transaction_history, clients_state),
-        "chargeback" => handle_chargeback(transaction, transaction_history, clients_state),
-        _ => eprintln!("Error: unrecognized transaction type {}", transaction.tx_type)
+    /// Closes the current business day by posting interest on every positive available balance
+    /// and charging overdraft interest on every overdrawn balance, each posting with its own system transaction id.
+    /// Postings are kept in the interest history only, so they never take the place of a client's transaction.
+    /// Locked and closed accounts neither earn nor get charged interest.
+    /// 
+    /// `at` - end of the business day, None when the day is closed by an `eod` row without timestamp
+    fn close_day(&mut self, at: Option<Timestamp>) {
+        let Some(policy) = self.config.interest else {
+            return;
+        };
+        let day = self.business_day;
+        let mut clients: Vec<ClientId> = self.clients_state.keys().copied().collect();
+        clients.sort_unstable();
+        for client in clients {
+            let before = self.snapshot(client);
+            let client_state = &self.clients_state[&client];
+            let earned = policy.daily_interest(client_state.available);
+            let charged = policy.daily_overdraft_interest(client_state.overdrawn);
+            if (earned <= 0.0 && charged <= 0.0) || client_state.status.is_locked() {
+                continue;
+            }
+            let Some(tx) = self.next_system_tx(client) else {
+                eprintln!("Error: no transaction id left to post interest for client {client}");
+                break;
+            };
+            // A balance is either positive or overdrawn, at most one of them is positive
+            let amount = earned.max(charged);
+            let posting = Transaction {
+                tx_type: TransactionType::Interest,
+                client,
+                tx,
+                amount: Some(amount),
+                operator: None,
+                timestamp: at,
+                seq: None,
+                currency: None,
+            };
+            let available = Account::Client(client, ClientBalance::Available);
+            let (entry, signed) = if charged > 0.0 {
+                (LedgerEntry::new(&posting, available, Account::InterestIncome, amount), -amount)
+            } else {
+                (LedgerEntry::new(&posting, Account::InterestExpense, available, amount), amount)
+            };
+            let client_state = self.clients_state.get_mut(&client).unwrap();
+            post(&mut self.ledger, client_state, entry);
+            client_state.refresh_overdrawn();
+            self.record(&posting, before);
+            self.interest_history.entry(client).or_default().push(InterestEntry { tx, day, amount: signed });
+        }
+        self.business_day = day.map(|day| day + 1);
+    }
+
+    /// Allocates the next system transaction id for `client`, downwards from the highest transaction id,
+    /// skipping the ids the client already used. None once every id was allocated.
+    fn next_system_tx(&mut self, client: ClientId) -> Option<TransactionId> {
+        loop {
+            let tx = TransactionId::MAX.checked_sub(self.system_txs)?;
+            self.system_txs = self.system_txs.checked_add(1)?;
+            if !self.transaction_history.contains_key(&(client, tx)) {
+                return Some(tx);
+            }
+        }
     }
-}
 
+    /// Closes the business days that ended before `now` when interest accrues, then clears the pending
+    /// deposits whose clearing delay elapsed and settles the disputes still open at their resolution deadline
+    /// according to the deadline policy.
+    /// 
+    /// Called with the timestamp of each transaction, can also be called to move time forward without a transaction.
+    pub fn advance_time(&mut self, now: Timestamp) {
+        self.close_days_ended(now);
+        self.settle_due(now);
+    }
+
+    /// Closes the business days that ended by `now` when interest accrues, posting their interest
+    /// once what was due before the end of each day is settled.
+    pub fn close_days_ended(&mut self, now: Timestamp) {
+        if let Some(policy) = self.config.interest {
+            let today = policy.business_day(now);
+            let open_day = *self.business_day.get_or_insert(today);
+            for day in open_day..today {
+                self.settle_due(policy.day_end(day) - 1);
+                self.close_day(Some(policy.day_end(day)));
+            }
+        }
+    }
+
+    /// Clears the pending deposits and settles the open disputes due at the given time.
+    fn settle_due(&mut self, now: Timestamp) {
+        while let Some(Reverse((clear_at, client, tx))) = self.pending_deposits.peek().copied() {
+            if clear_at > now {
+                break;
+            }
+            self.pending_deposits.pop();
+            let before = self.snapshot(client);
+            let clearing = Transaction {
+                tx_type: TransactionType::Clear,
+                client,
+                tx,
+                amount: None,
+                operator: None,
+                timestamp: Some(clear_at),
+                seq: None,
+                currency: None,
+            };
+            self.handle_clearing(&clearing, ClearingState::Cleared);
+            self.record(&clearing, before);
+        }
+        while let Some(Reverse((deadline, client, tx))) = self.open_disputes.peek().copied() {
+            if deadline > now {
+                break;
+            }
+            self.open_disputes.pop();
+            let Some(referenced_transaction) = self.transaction_history.get(&(client, tx)) else {
+                continue;
+            };
+            // The dispute may have been settled, or disputed again with a later deadline
+            let still_open = referenced_transaction.dispute_state == DisputeState::Disputed
+                && referenced_transaction.disputed_at.zip(self.config.dispute_deadline).map(|(at, delay)| at.saturating_add(delay))
+                    == Some(deadline);
+            if !still_open {
+                continue;
+            }
+            let before = self.snapshot(client);
+            let tx_type = match self.config.deadline_policy {
+                DeadlinePolicy::Resolve => TransactionType::Resolve,
+                DeadlinePolicy::Chargeback => TransactionType::Chargeback,
+            };
+            let settlement = Transaction {
+                tx_type,
+                client,
+                tx,
+                amount: None,
+                operator: None,
+                timestamp: Some(deadline),
+                seq: None,
+                currency: None,
+            };
+            match self.config.deadline_policy {
+                DeadlinePolicy::Resolve => self.handle_resolve(&settlement, true),
+                DeadlinePolicy::Chargeback => self.handle_chargeback(&settlement, true),
+            }
+            if let Some(client_state) = self.clients_state.get_mut(&client) {
+                client_state.refresh_overdrawn();
+            }
+            self.record(&settlement, before);
+        }
+    }
+
+    /// Dispatches receiving transaction to the correct handler.
+    /// 
+    /// Transaction type must be one of "deposit", "withdrawal", "dispute", "resolve", "chargeback", "refund",
+    /// "representment", "arbitration", "clear", "return", or one of the admin types "unlock", "freeze", "close"
+    /// 
+    /// Client operations are only applied if the permission matrix allows them for the account status, except for the chargeback cycle
+    /// following a first chargeback.
+    /// 
+    /// A transaction whose timestamp is older than the previous timestamp of the same client is ignored.
+    /// 
+    /// An "eod" row closes the current business day, its client and tx columns are ignored.
+    /// 
+    /// In audit mode, the invariants are checked on the client of the transaction once handled.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `transaction` - the current transaction
+    pub fn handle_transaction(&mut self, transaction: &Transaction) {
+        self.rows += 1;
+        if transaction.tx_type == TransactionType::Eod {
+            if let Some(timestamp) = transaction.timestamp {
+                self.advance_time(timestamp);
+            }
+            let at = self.business_day.zip(self.config.interest).map(|(day, policy)| policy.day_end(day));
+            self.close_day(at.or(transaction.timestamp));
+            return;
+        }
+        if let Some(timestamp) = transaction.timestamp {
+            let last_timestamp = self.last_timestamps.entry(transaction.client).or_insert(timestamp);
+            if timestamp < *last_timestamp {
+                eprintln!(
+                    "Error: transaction {} of client {} goes back in time ({} < {})",
+                    transaction.tx, transaction.client, timestamp, last_timestamp
+                );
+                return;
+            }
+            *last_timestamp = timestamp;
+            self.advance_time(timestamp);
+        }
+        let known = self.clients_state.contains_key(&transaction.client);
+        let before = self.snapshot(transaction.client);
+        let audit = self.config.audit.then(|| AuditSnapshot {
+            client_state: self.clients_state.get(&transaction.client).copied().unwrap_or_default(),
+            dispute_state: self
+                .transaction_history
+                .get(&(transaction.client, transaction.tx))
+                .map(|summary| summary.dispute_state),
+        });
+        match transaction.tx_type {
+            TransactionType::Deposit => self.handle_deposit(transaction),
+            TransactionType::Withdrawal => self.handle_withdrawal(transaction),
+            TransactionType::Dispute => self.handle_dispute(transaction),
+            TransactionType::Resolve => self.handle_resolve(transaction, false),
+            TransactionType::Chargeback => self.handle_chargeback(transaction, false),
+            TransactionType::Refund => self.handle_refund(transaction),
+            TransactionType::Representment => self.handle_chargeback_reversal(transaction, DisputeState::ChargedBack, DisputeState::Represented),
+            TransactionType::Arbitration => self.handle_chargeback_reversal(transaction, DisputeState::PreArbitration, DisputeState::Arbitrated),
+            TransactionType::Clear => self.handle_clearing(transaction, ClearingState::Cleared),
+            TransactionType::Return => self.handle_clearing(transaction, ClearingState::Returned),
+            TransactionType::Unlock => self.handle_admin(transaction, AdminAction::Unlock),
+            TransactionType::Freeze => self.handle_admin(transaction, AdminAction::Freeze),
+            TransactionType::Close => self.handle_admin(transaction, AdminAction::Close),
+            TransactionType::Eod | TransactionType::Interest | TransactionType::Unrecognized => eprintln!(
+                "Error: unrecognized type of transaction {} of client {}",
+                transaction.tx, transaction.client
+            ),
+        }
+        if let Some(client_state) = self.clients_state.get_mut(&transaction.client) {
+            client_state.refresh_overdrawn();
+            if !known {
+                self.clients_order.push(transaction.client);
+            }
+        }
+        self.record(transaction, before);
+        if let Some(before) = audit {
+            self.audit(transaction, &before);
+        }
+    }
+}
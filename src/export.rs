@@ -0,0 +1,68 @@
+use std::collections::BTreeSet;
+use std::io::{BufWriter, Write};
+
+use crate::engine::Timestamp;
+use crate::ledger::{Account, LedgerEntry};
+
+/// Date of the entries without timestamp, and of the beancount `open` directives.
+const EPOCH_DATE: &str = "1970-01-01";
+
+fn date(timestamp: Option<Timestamp>) -> String {
+    timestamp
+        .and_then(|timestamp| chrono::DateTime::from_timestamp(i64::try_from(timestamp).ok()?, 0))
+        .map(|datetime| datetime.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| EPOCH_DATE.to_string())
+}
+
+/// Writes ledger entries as a ledger-cli journal, also readable by hledger.
+/// Each entry is a transaction with two postings, tagged with the client and transaction ids.
+///
+/// # Arguments
+///
+/// `entries` - entries kept by the ledger, in order
+/// `commodity` - commodity the amounts are expressed in, e.g. USD
+/// `to` - destination that should implement the Write trait
+pub fn ledger_writer(entries: &[LedgerEntry], commodity: &str, to: impl Write) -> Result<(), std::io::Error> {
+    let mut stream = BufWriter::new(to);
+    for entry in entries {
+        writeln!(stream, "{} {} ; client:{}, tx:{}", date(entry.timestamp), entry.kind, entry.client, entry.tx)?;
+        writeln!(stream, "    {}  {:.4} {}", entry.debit, entry.amount, commodity)?;
+        writeln!(stream, "    {}  {:.4} {}", entry.credit, -entry.amount, commodity)?;
+        writeln!(stream)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes ledger entries as a beancount file.
+/// Every account used is opened first, then each entry is a transaction with two postings.
+///
+/// # Arguments
+///
+/// `entries` - entries kept by the ledger, in order
+/// `commodity` - currency the amounts are expressed in, e.g. USD
+/// `to` - destination that should implement the Write trait
+pub fn beancount_writer(entries: &[LedgerEntry], commodity: &str, to: impl Write) -> Result<(), std::io::Error> {
+    let mut stream = BufWriter::new(to);
+    writeln!(stream, "option \"operating_currency\" \"{commodity}\"")?;
+    writeln!(stream)?;
+    let accounts: BTreeSet<Account> = entries.iter().flat_map(|entry| [entry.debit, entry.credit]).collect();
+    for account in accounts {
+        writeln!(stream, "{EPOCH_DATE} open {account} {commodity}")?;
+    }
+    for entry in entries {
+        writeln!(stream)?;
+        writeln!(
+            stream,
+            "{} * \"{}\" \"client {}, tx {}\"",
+            date(entry.timestamp),
+            entry.kind,
+            entry.client,
+            entry.tx
+        )?;
+        writeln!(stream, "  {}  {:.4} {}", entry.debit, entry.amount, commodity)?;
+        writeln!(stream, "  {}  {:.4} {}", entry.credit, -entry.amount, commodity)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
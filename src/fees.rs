@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::engine::TransactionId;
+
+/// Kind of transaction a fee can be charged on.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeKind {
+    Withdrawal,
+    Chargeback,
+    /// Charged on the part of a withdrawal drawn on the credit line
+    Overdraft,
+    /// Charged on deposits and withdrawals made in another currency than the engine's
+    Conversion,
+}
+
+/// How a fee is computed from the transaction amount.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeModel {
+    /// `value` is charged as is
+    Flat,
+    /// `value` is a percentage of the transaction amount
+    Percentage,
+}
+
+/// Represents a line of the fee schedule.
+///
+/// Several rules with different `min_amount` for the same kind make a tiered schedule:
+/// the rule with the highest `min_amount` lower or equal to the transaction amount applies.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FeeRule {
+    pub kind: FeeKind,
+    pub model: FeeModel,
+    /// Lower bound (inclusive) of the tier, None means 0
+    pub min_amount: Option<f64>,
+    pub value: f64,
+}
+
+impl FeeRule {
+    fn get_min_amount(&self) -> f64 {
+        self.min_amount.unwrap_or_default()
+    }
+
+    fn compute(&self, amount: f64) -> f64 {
+        match self.model {
+            FeeModel::Flat => self.value,
+            FeeModel::Percentage => amount * self.value / 100.0,
+        }
+    }
+}
+
+/// Fee rules per kind of transaction, sorted by tier.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FeeSchedule {
+    rules: HashMap<FeeKind, Vec<FeeRule>>,
+}
+
+impl FeeSchedule {
+    pub fn new(rules: impl IntoIterator<Item = FeeRule>) -> Self {
+        let mut schedule = FeeSchedule::default();
+        for rule in rules {
+            schedule.rules.entry(rule.kind).or_default().push(rule);
+        }
+        for tiers in schedule.rules.values_mut() {
+            tiers.sort_by(|a, b| a.get_min_amount().total_cmp(&b.get_min_amount()));
+        }
+        schedule
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Computes the fee charged on a transaction of the given kind and amount, 0 if no rule applies.
+    pub fn fee_for(&self, kind: FeeKind, amount: f64) -> f64 {
+        self.rules
+            .get(&kind)
+            .and_then(|tiers| tiers.iter().rev().find(|rule| rule.get_min_amount() <= amount))
+            .map(|rule| rule.compute(amount))
+            .unwrap_or_default()
+    }
+}
+
+/// Represents a fee charged to a client, kept apart from the transaction it was charged on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeEntry {
+    pub kind: FeeKind,
+    /// Transaction the fee was charged on
+    pub tx: TransactionId,
+    pub amount: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiered_schedule_picks_highest_matching_tier() {
+        let schedule = FeeSchedule::new([
+            FeeRule { kind: FeeKind::Withdrawal, model: FeeModel::Percentage, min_amount: Some(100.0), value: 1.0 },
+            FeeRule { kind: FeeKind::Withdrawal, model: FeeModel::Flat, min_amount: None, value: 0.5 },
+        ]);
+
+        assert_eq!(schedule.fee_for(FeeKind::Withdrawal, 10.0), 0.5);
+        assert_eq!(schedule.fee_for(FeeKind::Withdrawal, 200.0), 2.0);
+        assert_eq!(schedule.fee_for(FeeKind::Chargeback, 200.0), 0.0);
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+
+use crate::admin::OperatorId;
+use crate::engine::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// Amounts are generated in ten-thousandths, the precision of the output.
+const UNITS: f64 = 10_000.0;
+/// Largest amount of a deposit or withdrawal, in ten-thousandths.
+const MAX_AMOUNT: u64 = 1_000 * UNITS as u64;
+/// Recent deposits kept per client as dispute targets, bounding memory on large workloads.
+const DISPUTABLE_DEPOSITS: usize = 64;
+/// Operator unlocking the clients locked by a chargeback.
+const OPERATOR: OperatorId = 1;
+
+/// Malformed or unusual transaction sprinkled in the workload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeCase {
+    /// Deposit reusing a tx id of the client
+    DuplicateId,
+    /// Deposit to a client locked by a chargeback
+    DepositOnLocked,
+    /// Withdrawal exceeding the available funds
+    Overdraw,
+    /// Dispute of a tx id that was never used
+    UnknownDispute,
+    /// Dispute of another client's deposit
+    ForeignDispute,
+    /// Resolve or chargeback of a transaction that is not disputed
+    UndisputedSettle,
+}
+
+const EDGE_CASES: [EdgeCase; 6] = [
+    EdgeCase::DuplicateId,
+    EdgeCase::DepositOnLocked,
+    EdgeCase::Overdraw,
+    EdgeCase::UnknownDispute,
+    EdgeCase::ForeignDispute,
+    EdgeCase::UndisputedSettle,
+];
+
+/// Shape of the generated workload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneratorConfig {
+    /// The same seed always yields the same transactions
+    pub seed: u64,
+    /// Number of clients, at least one client is generated
+    pub clients: ClientId,
+    /// Relative weight of deposits among deposits and withdrawals
+    pub deposit_weight: u32,
+    /// Relative weight of withdrawals among deposits and withdrawals
+    pub withdrawal_weight: u32,
+    /// Share of the rows that are disputes, resolves or chargebacks
+    pub dispute_ratio: f64,
+    /// Share of the disputes ending in a chargeback, the others being resolved
+    pub chargeback_ratio: f64,
+    /// Share of the rows that are edge cases
+    pub edge_case_ratio: f64,
+    /// Share of the rows unlocking a client locked by a chargeback, as an operator would after review
+    pub unlock_ratio: f64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            seed: 0,
+            clients: 1_000,
+            deposit_weight: 10,
+            withdrawal_weight: 8,
+            dispute_ratio: 0.01,
+            chargeback_ratio: 0.1,
+            edge_case_ratio: 0.0,
+            unlock_ratio: 0.001,
+        }
+    }
+}
+
+/// SplitMix64, small and stable across platforms and releases so that a seed always yields the same workload.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n`, `n` being positive.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniform value in `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+}
+
+/// What the generator knows of a client, mirroring the engine closely enough to produce valid transactions.
+#[derive(Debug, Default)]
+struct GeneratedClient {
+    /// In ten-thousandths
+    available: i64,
+    /// Most recent deposits that are not disputed, with their amount
+    deposits: VecDeque<(TransactionId, u64)>,
+    locked: bool,
+    /// Position of the client among the active or the locked clients
+    position: usize,
+}
+
+/// Generates a seeded, reproducible stream of transactions for the engine.
+///
+/// Withdrawals stay within the available funds, disputes reference deposits of the same client,
+/// resolves and chargebacks settle open disputes, and locked clients stop transacting until unlocked, edge cases aside.
+/// Every row is generated in constant time, whatever the number of clients.
+/// The stream ends once every tx id has been used.
+#[derive(Debug)]
+pub struct Generator {
+    config: GeneratorConfig,
+    rng: Rng,
+    next_tx: Option<TransactionId>,
+    clients: Vec<GeneratedClient>,
+    /// Clients that are not locked, in no particular order
+    active: Vec<ClientId>,
+    /// Clients locked by a chargeback, in no particular order
+    locked: Vec<ClientId>,
+    /// Disputed deposits not settled yet, with their client and amount
+    open_disputes: Vec<(ClientId, TransactionId, u64)>,
+}
+
+fn transaction(tx_type: TransactionType, client: ClientId, tx: TransactionId, amount: Option<u64>) -> Transaction {
+    Transaction {
+        tx_type,
+        client,
+        tx,
+        amount: amount.map(|amount| amount as f64 / UNITS),
+        operator: None,
+        timestamp: None,
+        seq: None,
+        currency: None,
+    }
+}
+
+impl Generator {
+    /// Creates a generator for the given workload, with at least one client since every row needs one.
+    pub fn new(config: GeneratorConfig) -> Self {
+        let config = GeneratorConfig { clients: config.clients.max(1), ..config };
+        Generator {
+            config,
+            rng: Rng(config.seed),
+            next_tx: Some(1),
+            clients: (0..config.clients)
+                .map(|client| GeneratedClient { position: usize::from(client), ..Default::default() })
+                .collect(),
+            active: (1..=config.clients).collect(),
+            locked: Vec::new(),
+            open_disputes: Vec::new(),
+        }
+    }
+
+    fn allocate_tx(&mut self) -> Option<TransactionId> {
+        let tx = self.next_tx?;
+        self.next_tx = tx.checked_add(1);
+        Some(tx)
+    }
+
+    fn client_mut(&mut self, client: ClientId) -> &mut GeneratedClient {
+        &mut self.clients[usize::from(client) - 1]
+    }
+
+    fn random_client(&mut self) -> ClientId {
+        self.rng.below(u64::from(self.config.clients)) as ClientId + 1
+    }
+
+    /// Random client that is not locked, or any client if they all are.
+    fn random_active_client(&mut self) -> ClientId {
+        if self.active.is_empty() {
+            return self.random_client();
+        }
+        self.active[self.rng.below(self.active.len() as u64) as usize]
+    }
+
+    /// Random client locked by a chargeback, if any.
+    fn random_locked_client(&mut self) -> Option<ClientId> {
+        if self.locked.is_empty() {
+            return None;
+        }
+        Some(self.locked[self.rng.below(self.locked.len() as u64) as usize])
+    }
+
+    /// Moves a client from the active clients to the locked ones, or back.
+    fn set_locked(&mut self, client: ClientId, locked: bool) {
+        let index = usize::from(client) - 1;
+        if self.clients[index].locked == locked {
+            return;
+        }
+        let (from, to) = if locked { (&mut self.active, &mut self.locked) } else { (&mut self.locked, &mut self.active) };
+        let position = self.clients[index].position;
+        from.swap_remove(position);
+        if let Some(moved) = from.get(position) {
+            self.clients[usize::from(*moved) - 1].position = position;
+        }
+        self.clients[index].position = to.len();
+        self.clients[index].locked = locked;
+        to.push(client);
+    }
+
+    fn random_amount(&mut self, max: u64) -> u64 {
+        self.rng.below(max.max(1)) + 1
+    }
+
+    fn deposit(&mut self, client: ClientId) -> Option<Transaction> {
+        let tx = self.allocate_tx()?;
+        let amount = self.random_amount(MAX_AMOUNT);
+        let generated = self.client_mut(client);
+        if !generated.locked {
+            generated.available += amount as i64;
+            if generated.deposits.len() == DISPUTABLE_DEPOSITS {
+                generated.deposits.pop_front();
+            }
+            generated.deposits.push_back((tx, amount));
+        }
+        Some(transaction(TransactionType::Deposit, client, tx, Some(amount)))
+    }
+
+    fn withdrawal(&mut self, client: ClientId, amount: u64) -> Option<Transaction> {
+        let tx = self.allocate_tx()?;
+        let generated = self.client_mut(client);
+        if !generated.locked && generated.available >= amount as i64 {
+            generated.available -= amount as i64;
+        }
+        Some(transaction(TransactionType::Withdrawal, client, tx, Some(amount)))
+    }
+
+    /// Deposit or withdrawal of an active client, following the type mix.
+    fn movement(&mut self) -> Option<Transaction> {
+        let client = self.random_active_client();
+        let weights = u64::from(self.config.deposit_weight) + u64::from(self.config.withdrawal_weight);
+        let available = self.client_mut(client).available;
+        if weights > 0 && self.rng.below(weights) >= u64::from(self.config.deposit_weight) && available > 0 {
+            let amount = self.random_amount((available as u64).min(MAX_AMOUNT));
+            self.withdrawal(client, amount)
+        } else {
+            self.deposit(client)
+        }
+    }
+
+    /// Settles an open dispute, or disputes a recent deposit of an active client.
+    fn dispute_cycle(&mut self) -> Option<Transaction> {
+        if !self.open_disputes.is_empty() && self.rng.chance(0.5) {
+            let index = self.rng.below(self.open_disputes.len() as u64) as usize;
+            let (client, tx, amount) = self.open_disputes.swap_remove(index);
+            let chargeback = self.rng.chance(self.config.chargeback_ratio);
+            // Settling a dispute of a locked client is left in, the engine ignores it
+            if !self.client_mut(client).locked && chargeback {
+                self.set_locked(client, true);
+            } else if !self.client_mut(client).locked {
+                self.client_mut(client).available += amount as i64;
+            }
+            return Some(transaction(if chargeback { TransactionType::Chargeback } else { TransactionType::Resolve }, client, tx, None));
+        }
+        let client = self.random_active_client();
+        let generated = &mut self.clients[usize::from(client) - 1];
+        if generated.locked || generated.deposits.is_empty() {
+            return self.movement();
+        }
+        let index = self.rng.below(generated.deposits.len() as u64) as usize;
+        let (tx, amount) = generated.deposits.swap_remove_back(index)?;
+        generated.available -= amount as i64;
+        self.open_disputes.push((client, tx, amount));
+        Some(transaction(TransactionType::Dispute, client, tx, None))
+    }
+
+    /// Unlocks a client locked by a chargeback.
+    fn unlock(&mut self, client: ClientId) -> Option<Transaction> {
+        let tx = self.allocate_tx()?;
+        self.set_locked(client, false);
+        Some(Transaction { operator: Some(OPERATOR), ..transaction(TransactionType::Unlock, client, tx, None) })
+    }
+
+    fn edge_case(&mut self) -> Option<Transaction> {
+        let edge_case = EDGE_CASES[self.rng.below(EDGE_CASES.len() as u64) as usize];
+        let client = self.random_client();
+        let generated = &self.clients[usize::from(client) - 1];
+        let (available, deposit) = (generated.available, generated.deposits.front().copied());
+        let locked_client = self.random_locked_client();
+        match (edge_case, deposit, locked_client) {
+            (EdgeCase::DuplicateId, Some((tx, _)), _) => {
+                let amount = self.random_amount(MAX_AMOUNT);
+                Some(transaction(TransactionType::Deposit, client, tx, Some(amount)))
+            }
+            (EdgeCase::DepositOnLocked, _, Some(locked_client)) => self.deposit(locked_client),
+            (EdgeCase::Overdraw, _, _) => {
+                let amount = available.max(0) as u64 + self.random_amount(MAX_AMOUNT);
+                self.withdrawal(client, amount)
+            }
+            (EdgeCase::ForeignDispute, Some((tx, _)), _) if self.config.clients > 1 => {
+                let other = client % self.config.clients + 1;
+                Some(transaction(TransactionType::Dispute, other, tx, None))
+            }
+            (EdgeCase::UndisputedSettle, Some((tx, _)), _) => {
+                let tx_type = if self.rng.chance(0.5) { TransactionType::Resolve } else { TransactionType::Chargeback };
+                Some(transaction(tx_type, client, tx, None))
+            }
+            _ => {
+                // Ids are allocated upwards, the next one has not been used yet
+                let tx = self.next_tx?;
+                Some(transaction(TransactionType::Dispute, client, tx, None))
+            }
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        let draw = self.rng.unit();
+        if draw < self.config.edge_case_ratio {
+            self.edge_case()
+        } else if draw < self.config.edge_case_ratio + self.config.dispute_ratio {
+            self.dispute_cycle()
+        } else if draw < self.config.edge_case_ratio + self.config.dispute_ratio + self.config.unlock_ratio {
+            match self.random_locked_client() {
+                Some(client) => self.unlock(client),
+                None => self.movement(),
+            }
+        } else {
+            self.movement()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_yields_same_transactions() {
+        let config = GeneratorConfig { clients: 20, dispute_ratio: 0.1, edge_case_ratio: 0.1, ..Default::default() };
+        let first: Vec<Transaction> = Generator::new(config).take(1000).collect();
+        let again: Vec<Transaction> = Generator::new(config).take(1000).collect();
+        let other: Vec<Transaction> = Generator::new(GeneratorConfig { seed: 1, ..config }).take(1000).collect();
+
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        for kind in [
+            TransactionType::Deposit,
+            TransactionType::Withdrawal,
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ] {
+            assert!(first.iter().any(|transaction| transaction.tx_type == kind), "no {} generated", kind.as_str());
+        }
+    }
+
+    #[test]
+    fn zero_clients_generate_rows_for_one_client() {
+        let transactions: Vec<Transaction> = Generator::new(GeneratorConfig { clients: 0, ..Default::default() }).take(100).collect();
+
+        assert_eq!(transactions.len(), 100);
+        assert!(transactions.iter().all(|transaction| transaction.client == 1));
+    }
+
+    #[test]
+    fn locked_clients_get_unlocked() {
+        let mut generator = Generator::new(GeneratorConfig { clients: 100, ..Default::default() });
+        let unlocks = generator.by_ref().take(200_000).filter(|transaction| transaction.tx_type == TransactionType::Unlock).count();
+
+        assert!(unlocks > 0);
+        assert!(generator.locked.len() < 10, "{} clients locked", generator.locked.len());
+        for (position, client) in generator.active.iter().enumerate() {
+            assert_eq!(generator.clients[usize::from(*client) - 1].position, position);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+
+use crate::engine::{ClearingState, ClientId, DisputeState, Timestamp, TransactionId, TransactionKind, TransactionSummary};
+
+/// Transactions per page, pages being allocated as transaction ids get used.
+const PAGE_BITS: u32 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// Pages per directory, directories being allocated as pages get used.
+const DIRECTORY_BITS: u32 = 10;
+const DIRECTORY_SIZE: usize = 1 << DIRECTORY_BITS;
+/// Directories covering every transaction id.
+const DIRECTORIES: usize = 1 << (TransactionId::BITS - PAGE_BITS - DIRECTORY_BITS);
+/// Transactions a page holds in a map before switching to columns, a transaction taking about 64 bytes in the map.
+const SPARSE_LIMIT: usize = PAGE_SIZE / 8;
+
+/// Bits of a packed state, 0 being an empty slot.
+const OCCUPIED: u16 = 1;
+const WITHDRAWAL: u16 = 1 << 1;
+const DISPUTE_SHIFT: u16 = 2;
+const DISPUTE_MASK: u16 = 0b111 << DISPUTE_SHIFT;
+const CLEARING_SHIFT: u16 = 5;
+const CLEARING_MASK: u16 = 0b11 << CLEARING_SHIFT;
+const HAS_TIMESTAMP: u16 = 1 << 7;
+const HAS_DISPUTED_AT: u16 = 1 << 8;
+
+fn pack(summary: &TransactionSummary) -> u16 {
+    let kind = match summary.kind {
+        TransactionKind::Deposit => 0,
+        TransactionKind::Withdrawal => WITHDRAWAL,
+    };
+    let dispute_state = match summary.dispute_state {
+        DisputeState::Undisputed => 0,
+        DisputeState::Disputed => 1,
+        DisputeState::ChargedBack => 2,
+        DisputeState::Represented => 3,
+        DisputeState::PreArbitration => 4,
+        DisputeState::Arbitrated => 5,
+    };
+    let clearing_state = match summary.clearing_state {
+        ClearingState::Pending => 0,
+        ClearingState::Cleared => 1,
+        ClearingState::Returned => 2,
+    };
+    let timestamps = if summary.timestamp.is_some() { HAS_TIMESTAMP } else { 0 }
+        | if summary.disputed_at.is_some() { HAS_DISPUTED_AT } else { 0 };
+    OCCUPIED | kind | dispute_state << DISPUTE_SHIFT | clearing_state << CLEARING_SHIFT | timestamps
+}
+
+fn unpack(state: u16, amount: f64, timestamp: Timestamp, disputed_at: Timestamp) -> TransactionSummary {
+    TransactionSummary {
+        kind: if state & WITHDRAWAL == 0 { TransactionKind::Deposit } else { TransactionKind::Withdrawal },
+        amount,
+        dispute_state: match (state & DISPUTE_MASK) >> DISPUTE_SHIFT {
+            0 => DisputeState::Undisputed,
+            1 => DisputeState::Disputed,
+            2 => DisputeState::ChargedBack,
+            3 => DisputeState::Represented,
+            4 => DisputeState::PreArbitration,
+            _ => DisputeState::Arbitrated,
+        },
+        timestamp: (state & HAS_TIMESTAMP != 0).then_some(timestamp),
+        disputed_at: (state & HAS_DISPUTED_AT != 0).then_some(disputed_at),
+        clearing_state: match (state & CLEARING_MASK) >> CLEARING_SHIFT {
+            0 => ClearingState::Pending,
+            1 => ClearingState::Cleared,
+            _ => ClearingState::Returned,
+        },
+    }
+}
+
+/// Transactions of consecutive ids, stored column by column.
+#[derive(Debug)]
+struct DensePage {
+    states: Box<[u16]>,
+    clients: Box<[ClientId]>,
+    amounts: Box<[f64]>,
+    /// Only allocated once a transaction of the page has a timestamp
+    timestamps: Option<Box<[Timestamp]>>,
+    /// Only allocated once a transaction of the page is disputed with a timestamp
+    disputed_at: Option<Box<[Timestamp]>>,
+}
+
+impl DensePage {
+    fn new() -> Self {
+        DensePage {
+            states: vec![0; PAGE_SIZE].into_boxed_slice(),
+            clients: vec![0; PAGE_SIZE].into_boxed_slice(),
+            amounts: vec![0.0; PAGE_SIZE].into_boxed_slice(),
+            timestamps: None,
+            disputed_at: None,
+        }
+    }
+
+    fn get(&self, slot: usize) -> TransactionSummary {
+        let timestamp = self.timestamps.as_ref().map_or(0, |timestamps| timestamps[slot]);
+        let disputed_at = self.disputed_at.as_ref().map_or(0, |disputed_at| disputed_at[slot]);
+        unpack(self.states[slot], self.amounts[slot], timestamp, disputed_at)
+    }
+
+    fn set(&mut self, slot: usize, client: ClientId, summary: &TransactionSummary) {
+        self.states[slot] = pack(summary);
+        self.clients[slot] = client;
+        self.amounts[slot] = summary.amount;
+        if let Some(timestamp) = summary.timestamp {
+            self.timestamps.get_or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice())[slot] = timestamp;
+        }
+        if let Some(disputed_at) = summary.disputed_at {
+            self.disputed_at.get_or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice())[slot] = disputed_at;
+        }
+    }
+}
+
+/// Transactions of consecutive ids, kept in a map by slot while few ids of the page are used.
+#[derive(Debug)]
+enum Page {
+    Sparse(HashMap<u16, (ClientId, TransactionSummary)>),
+    Dense(Box<DensePage>),
+}
+
+impl Page {
+    /// Client of the transaction in the slot, if any.
+    fn client(&self, slot: usize) -> Option<ClientId> {
+        match self {
+            Page::Sparse(transactions) => transactions.get(&(slot as u16)).map(|(client, _)| *client),
+            Page::Dense(page) => (page.states[slot] != 0).then(|| page.clients[slot]),
+        }
+    }
+
+    fn get(&self, slot: usize) -> Option<TransactionSummary> {
+        match self {
+            Page::Sparse(transactions) => transactions.get(&(slot as u16)).map(|(_, summary)| *summary),
+            Page::Dense(page) => (page.states[slot] != 0).then(|| page.get(slot)),
+        }
+    }
+
+    /// Stores the transaction in the slot, switching to columns once the page holds more than `SPARSE_LIMIT` transactions.
+    fn set(&mut self, slot: usize, client: ClientId, summary: &TransactionSummary) {
+        match self {
+            Page::Sparse(transactions) => {
+                transactions.insert(slot as u16, (client, *summary));
+                if transactions.len() > SPARSE_LIMIT {
+                    let mut page = DensePage::new();
+                    for (slot, (client, summary)) in transactions.drain() {
+                        page.set(usize::from(slot), client, &summary);
+                    }
+                    *self = Page::Dense(Box::new(page));
+                }
+            }
+            Page::Dense(page) => page.set(slot, client, summary),
+        }
+    }
+}
+
+type Directory = Box<[Option<Page>]>;
+
+/// History of the deposits and withdrawals, identified by client id and transaction id respectively.
+///
+/// Transactions live in pages of consecutive ids, reached through a two-level table. A page first keeps its
+/// transactions in a map, so that sparse ids don't allocate whole pages. Once more than an eighth of its ids are used,
+/// it stores them column by column, 12 bytes each (a packed state, the client id and the amount), plus 8 bytes per
+/// timestamp in pages where the input has timestamps. A transaction reusing the id of another client's
+/// transaction is kept in a map aside.
+#[derive(Debug)]
+pub struct TransactionHistory {
+    directories: Box<[Option<Directory>]>,
+    /// Transactions whose id is taken by a transaction of another client
+    overflow: HashMap<(ClientId, TransactionId), TransactionSummary>,
+    len: usize,
+}
+
+impl Default for TransactionHistory {
+    fn default() -> Self {
+        TransactionHistory {
+            directories: (0..DIRECTORIES).map(|_| None).collect(),
+            overflow: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+/// Position of a transaction id: directory, page within the directory and slot within the page.
+fn position(tx: TransactionId) -> (usize, usize, usize) {
+    let tx = tx as usize;
+    (tx >> (PAGE_BITS + DIRECTORY_BITS), (tx >> PAGE_BITS) & (DIRECTORY_SIZE - 1), tx & (PAGE_SIZE - 1))
+}
+
+impl TransactionHistory {
+    fn page(&self, tx: TransactionId) -> Option<(&Page, usize)> {
+        let (directory, page, slot) = position(tx);
+        let page = self.directories[directory].as_ref()?[page].as_ref()?;
+        Some((page, slot))
+    }
+
+    pub fn get(&self, &(client, tx): &(ClientId, TransactionId)) -> Option<TransactionSummary> {
+        match self.page(tx) {
+            Some((page, slot)) if page.client(slot) == Some(client) => page.get(slot),
+            _ => self.overflow.get(&(client, tx)).copied(),
+        }
+    }
+
+    /// Gives a copy of the transaction that is written back once dropped.
+    pub fn get_mut(&mut self, key: &(ClientId, TransactionId)) -> Option<SummaryMut<'_>> {
+        let summary = self.get(key)?;
+        Some(SummaryMut { history: self, key: *key, summary })
+    }
+
+    pub fn contains_key(&self, key: &(ClientId, TransactionId)) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, (client, tx): (ClientId, TransactionId), summary: TransactionSummary) {
+        let (directory, page, slot) = position(tx);
+        let page = self.directories[directory]
+            .get_or_insert_with(|| (0..DIRECTORY_SIZE).map(|_| None).collect())[page]
+            .get_or_insert_with(|| Page::Sparse(HashMap::new()));
+        match page.client(slot) {
+            None => {
+                page.set(slot, client, &summary);
+                self.len += 1;
+            }
+            Some(occupant) if occupant == client => page.set(slot, client, &summary),
+            Some(_) => {
+                if self.overflow.insert((client, tx), summary).is_none() {
+                    self.len += 1;
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Copy of a historized transaction, written back to the history once dropped.
+pub struct SummaryMut<'a> {
+    history: &'a mut TransactionHistory,
+    key: (ClientId, TransactionId),
+    summary: TransactionSummary,
+}
+
+impl Deref for SummaryMut<'_> {
+    type Target = TransactionSummary;
+
+    fn deref(&self) -> &TransactionSummary {
+        &self.summary
+    }
+}
+
+impl DerefMut for SummaryMut<'_> {
+    fn deref_mut(&mut self) -> &mut TransactionSummary {
+        &mut self.summary
+    }
+}
+
+impl Drop for SummaryMut<'_> {
+    fn drop(&mut self) {
+        let summary = self.summary;
+        self.history.insert(self.key, summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_round_trips_summaries() {
+        let summary = TransactionSummary {
+            kind: TransactionKind::Withdrawal,
+            amount: 1.5,
+            dispute_state: DisputeState::PreArbitration,
+            timestamp: Some(1646128800),
+            disputed_at: None,
+            clearing_state: ClearingState::Returned,
+        };
+        let mut history = TransactionHistory::default();
+        history.insert((1, 7), summary);
+        history.insert((2, 7), TransactionSummary { timestamp: None, ..summary });
+        history.insert((1, TransactionId::MAX), summary);
+        history.get_mut(&(1, 7)).unwrap().disputed_at = Some(1646132400);
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(&(1, 7)), Some(TransactionSummary { disputed_at: Some(1646132400), ..summary }));
+        assert_eq!(history.get(&(2, 7)), Some(TransactionSummary { timestamp: None, ..summary }));
+        assert_eq!(history.get(&(1, TransactionId::MAX)), Some(summary));
+        assert_eq!(history.get(&(1, 8)), None);
+        assert_eq!(history.get(&(3, 7)), None);
+    }
+
+    #[test]
+    fn sparse_page_switches_to_columns_once_filled() {
+        let summary = TransactionSummary {
+            kind: TransactionKind::Deposit,
+            amount: 2.0,
+            dispute_state: DisputeState::Undisputed,
+            timestamp: None,
+            disputed_at: None,
+            clearing_state: ClearingState::Cleared,
+        };
+        let mut history = TransactionHistory::default();
+        for tx in 0..=SPARSE_LIMIT as TransactionId {
+            assert!(matches!(history.page(tx), None | Some((Page::Sparse(_), _))));
+            history.insert((1, tx), TransactionSummary { amount: f64::from(tx), ..summary });
+        }
+        history.insert((2, 0), summary);
+
+        assert!(matches!(history.page(0), Some((Page::Dense(_), _))));
+        assert_eq!(history.len(), SPARSE_LIMIT + 2);
+        for tx in 0..=SPARSE_LIMIT as TransactionId {
+            assert_eq!(history.get(&(1, tx)), Some(TransactionSummary { amount: f64::from(tx), ..summary }));
+        }
+        assert_eq!(history.get(&(2, 0)), Some(summary));
+        assert_eq!(history.get(&(2, 1)), None);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::engine::{Timestamp, TransactionId};
+use crate::limits::SECONDS_PER_DAY;
+
+const DAYS_PER_YEAR: f64 = 365.0;
+
+/// Business day a timestamp falls in, counted in days since the Unix epoch, days ending `cutoff` seconds after midnight (UTC).
+pub fn business_day(timestamp: Timestamp, cutoff: Timestamp) -> u64 {
+    timestamp.saturating_sub(cutoff) / SECONDS_PER_DAY
+}
+
+/// Time at which the given business day ends, transactions from then on belong to the next day.
+/// Saturates at the latest timestamp for days ending after it.
+pub fn day_end(day: u64, cutoff: Timestamp) -> Timestamp {
+    day.saturating_add(1).saturating_mul(SECONDS_PER_DAY).saturating_add(cutoff)
+}
+
+/// How interest accrues on available balances.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InterestPolicy {
+    /// Annual rate as a percentage, accrued daily on the available balance
+    pub rate: f64,
+    /// Annual rate as a percentage, accrued daily on the overdrawn balance and charged to the client
+    pub overdraft_rate: f64,
+    /// Seconds after midnight (UTC) at which a business day ends
+    pub cutoff: Timestamp,
+}
+
+impl InterestPolicy {
+    pub fn business_day(&self, timestamp: Timestamp) -> u64 {
+        business_day(timestamp, self.cutoff)
+    }
+
+    pub fn day_end(&self, day: u64) -> Timestamp {
+        day_end(day, self.cutoff)
+    }
+
+    /// Interest earned by an available balance over one day, 0 for balances that are not positive.
+    pub fn daily_interest(&self, available: f64) -> f64 {
+        available.max(0.0) * self.rate / 100.0 / DAYS_PER_YEAR
+    }
+
+    /// Interest charged on an overdrawn balance over one day, 0 for balances that are not positive.
+    pub fn daily_overdraft_interest(&self, overdrawn: f64) -> f64 {
+        overdrawn.max(0.0) * self.overdraft_rate / 100.0 / DAYS_PER_YEAR
+    }
+}
+
+/// Represents interest posted to a client, or charged on its overdrawn balance when negative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterestEntry {
+    /// System transaction id of the posting
+    pub tx: TransactionId,
+    /// Business day the interest was accrued for, None when days are only closed by `eod` rows
+    pub day: Option<u64>,
+    /// Positive when earned by the client, negative when charged on an overdrawn balance
+    pub amount: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn business_day_ends_at_cutoff() {
+        let policy = InterestPolicy { rate: 3.65, overdraft_rate: 18.25, cutoff: 3600 };
+
+        assert_eq!(policy.business_day(3599), 0);
+        assert_eq!(policy.business_day(SECONDS_PER_DAY + 3599), 0);
+        assert_eq!(policy.business_day(SECONDS_PER_DAY + 3600), 1);
+        assert_eq!(policy.day_end(0), SECONDS_PER_DAY + 3600);
+        assert_eq!(policy.day_end(policy.business_day(Timestamp::MAX)), Timestamp::MAX);
+        assert_eq!(policy.daily_interest(1000.0), 0.1);
+        assert_eq!(policy.daily_interest(-10.0), 0.0);
+        assert_eq!(policy.daily_overdraft_interest(200.0), 0.1);
+    }
+}
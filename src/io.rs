@@ -1,6 +1,15 @@
-use std::{io::{Read, BufWriter, Write}, collections::HashMap, error::Error};
+use std::{io::{Read, BufReader, BufWriter, Write}, collections::HashMap, error::Error};
 
-use crate::engine::{ClientState, TransactionSummary, handle_transaction, Transaction, ClientId, TransactionId};
+use serde::Serialize;
+
+use crate::engine::{ClientState, CreditLimit, Engine, Transaction, ClientId};
+use crate::fees::{FeeRule, FeeSchedule};
+use crate::ledger::TrialBalance;
+use crate::limits::{ClientTier, LimitRule, Limits};
+use crate::parse::TransactionReader;
+use crate::reorder::ReorderBuffer;
+use crate::statement::{DailyClose, Statement};
+use crate::status::{AccountStatus, Permission, PermissionMatrix};
 
 
 /// Reads a source formated as a CSV and deserialize its content.
@@ -10,18 +19,283 @@ use crate::engine::{ClientState, TransactionSummary, handle_transaction, Transac
 /// 
 /// `from` - source that should implement the Read trait
 pub fn csv_reader(from: impl Read) -> Result<HashMap<ClientId, ClientState>, Box<dyn Error>> {
-    let mut transaction_history: HashMap<(ClientId, TransactionId), TransactionSummary> = HashMap::new();
-    let mut clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+    let mut engine = Engine::default();
+    csv_feed(from, &mut engine)?;
+    Ok(engine.clients_state)
+}
+
+/// Reads a source formated as a CSV and feeds each transaction to the engine.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+/// `engine` - engine handling the transactions
+pub fn csv_feed(from: impl Read, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+    let mut reader = TransactionReader::new(from)?;
+    let mut transaction = Transaction::default();
+    while reader.read(&mut transaction)? {
+        engine.handle_transaction(&transaction);
+    }
+    Ok(())
+}
 
+/// Reads a source formated as a CSV and deserialize its content with serde, row by row.
+/// Same result as `csv_reader`, which only deserializes with serde the rows its fast path does not handle.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+pub fn csv_reader_serde(from: impl Read) -> Result<HashMap<ClientId, ClientState>, Box<dyn Error>> {
     let mut reader = csv::ReaderBuilder::new()
         .trim(csv::Trim::All) // In order to handle whitespaces
         .from_reader(from);
-
+    let mut engine = Engine::default();
     for result in reader.deserialize() {
         let transaction: Transaction = result?;
-        handle_transaction(&transaction, &mut transaction_history, &mut clients_state);
+        engine.handle_transaction(&transaction);
+    }
+    Ok(engine.clients_state)
+}
+
+/// Reads a source formated as a CSV and feeds each transaction to the engine through a reorder buffer.
+/// The buffer is flushed once the source is exhausted.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+/// `engine` - engine handling the transactions
+/// `buffer` - buffer restoring the order of the transactions
+pub fn csv_feed_reordered(from: impl Read, engine: &mut Engine, buffer: &mut ReorderBuffer) -> Result<(), Box<dyn Error>> {
+    let mut reader = TransactionReader::new(from)?;
+    let mut transaction = Transaction::default();
+    while reader.read(&mut transaction)? {
+        buffer.push(transaction.clone(), engine);
+    }
+    buffer.finish(engine);
+    Ok(())
+}
+
+/// Reads a source formated as a CSV and feeds each transaction to the engine, closing each business day.
+/// The current day is closed once the source is exhausted.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+/// `engine` - engine handling the transactions, journaling its changes
+/// `close` - daily close collecting the statements
+pub fn csv_feed_daily(from: impl Read, engine: &mut Engine, close: &mut DailyClose) -> Result<(), Box<dyn Error>> {
+    let mut reader = TransactionReader::new(from)?;
+    let mut transaction = Transaction::default();
+    while reader.read(&mut transaction)? {
+        close.push(&transaction, engine);
+    }
+    close.finish(engine);
+    Ok(())
+}
+
+/// Reads a source formated as JSON Lines and deserialize its content.
+/// Each line from the source should be a JSON object with the fields of a transaction, as named by the CSV headers.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+pub fn json_reader(from: impl Read) -> Result<HashMap<ClientId, ClientState>, Box<dyn Error>> {
+    let mut engine = Engine::default();
+    json_feed(from, &mut engine)?;
+    Ok(engine.clients_state)
+}
+
+/// Reads a source formated as JSON Lines and feeds each transaction to the engine.
+/// Transactions are deserialized as on the serde path of `csv_reader`, so fields are validated the same way.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+/// `engine` - engine handling the transactions
+pub fn json_feed(from: impl Read, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+    for result in serde_json::Deserializer::from_reader(BufReader::new(from)).into_iter::<Transaction>() {
+        engine.handle_transaction(&result?);
+    }
+    Ok(())
+}
+
+/// Reads a fee schedule formated as a CSV.
+/// Each line from the source should represent a fee rule with columns `kind,model,min_amount,value`.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+pub fn fee_schedule_reader(from: impl Read) -> Result<FeeSchedule, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All) // In order to handle whitespaces
+        .from_reader(from);
+
+    let rules = reader.deserialize().collect::<Result<Vec<FeeRule>, _>>()?;
+    Ok(FeeSchedule::new(rules))
+}
+
+/// Reads a permission matrix formated as a CSV.
+/// Each line from the source should override a permission with columns `status,operation,allowed`.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+pub fn permissions_reader(from: impl Read) -> Result<PermissionMatrix, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All) // In order to handle whitespaces
+        .from_reader(from);
+
+    let permissions = reader.deserialize().collect::<Result<Vec<Permission>, _>>()?;
+    Ok(PermissionMatrix::new(permissions))
+}
+
+/// Reads limit rules and tier assignments formated as CSVs.
+/// 
+/// # Arguments
+/// 
+/// `rules` - source of rules with columns `client,tier,operation,max_amount,window,max_total,max_count,action`
+/// `tiers` - source of tier assignments with columns `client,tier`
+pub fn limits_reader(rules: impl Read, tiers: impl Read) -> Result<Limits, Box<dyn Error>> {
+    let mut rules_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All) // In order to handle whitespaces
+        .from_reader(rules);
+    let mut tiers_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(tiers);
+
+    let rules = rules_reader.deserialize().collect::<Result<Vec<LimitRule>, _>>()?;
+    let tiers = tiers_reader.deserialize().collect::<Result<Vec<ClientTier>, _>>()?;
+    Ok(Limits::new(rules, tiers))
+}
+
+/// Reads per-client credit limits formated as a CSV.
+/// Each line from the source should grant a credit line with columns `client,credit_limit`.
+/// 
+/// # Arguments
+/// 
+/// `from` - source that should implement the Read trait
+pub fn credit_limits_reader(from: impl Read) -> Result<HashMap<ClientId, f64>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All) // In order to handle whitespaces
+        .from_reader(from);
+
+    let credit_limits = reader.deserialize().collect::<Result<Vec<CreditLimit>, _>>()?;
+    Ok(credit_limits.into_iter().map(|line| (line.client, line.credit_limit)).collect())
+}
+
+/// Order of the rows written by `csv_writer`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RowOrder {
+    /// Sorted by client id
+    #[default]
+    ClientId,
+    /// Order of the given clients, e.g. first-seen order from `Engine::clients_by_first_seen`
+    AsGiven,
+}
+
+/// Clients written by `csv_writer`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RowFilter {
+    #[default]
+    All,
+    /// Only the clients whose `locked` column is true
+    Locked,
+    /// Only the clients with an available, held or total balance that is non-zero at the output precision
+    NonZero,
+}
+
+/// Options controlling which rows and columns `csv_writer` emits, and how.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputOptions {
+    /// Adds a `fees` column with the fees collected per client
+    pub fees: bool,
+    /// Replaces the `locked` column with a `status` column holding the account status
+    pub status: bool,
+    /// Adds a `pending` column with the deposited funds not cleared yet
+    pub pending: bool,
+    /// Adds an `overdrawn` column with how far available went below zero
+    pub overdrawn: bool,
+    pub order: RowOrder,
+    pub filter: RowFilter,
+    /// Number of decimals of the amounts, 4 by default
+    pub precision: usize,
+    /// Writes the header line, true by default
+    pub header: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            fees: false,
+            status: false,
+            pending: false,
+            overdrawn: false,
+            order: RowOrder::default(),
+            filter: RowFilter::default(),
+            precision: 4,
+            header: true,
+        }
+    }
+}
+
+impl OutputOptions {
+    fn keeps(&self, client_state: &ClientState) -> bool {
+        let non_zero = |amount: f64| (amount * 10f64.powi(self.precision as i32)).round() != 0.0;
+        match self.filter {
+            RowFilter::All => true,
+            RowFilter::Locked => client_state.status.is_locked(),
+            RowFilter::NonZero => [client_state.available, client_state.held, client_state.total].into_iter().any(non_zero),
+        }
+    }
+
+    /// Clients kept by the filter, in the requested order.
+    fn rows(&self, clients_state: impl IntoIterator<Item = (ClientId, ClientState)>) -> Vec<(ClientId, ClientState)> {
+        let mut rows: Vec<_> = clients_state.into_iter().filter(|(_, client_state)| self.keeps(client_state)).collect();
+        if self.order == RowOrder::ClientId {
+            rows.sort_unstable_by_key(|(client_id, _)| *client_id);
+        }
+        rows
+    }
+
+    fn round(&self, amount: f64) -> f64 {
+        let scale = 10f64.powi(self.precision as i32);
+        (amount * scale).round() / scale
+    }
+}
+
+/// Client's final state as written by `json_writer` and `json_lines_writer`, with the columns of `csv_writer`.
+#[derive(Serialize)]
+struct ClientRow {
+    client: ClientId,
+    available: f64,
+    held: f64,
+    total: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<AccountStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fees: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overdrawn: Option<f64>,
+}
+
+impl ClientRow {
+    fn new(client: ClientId, client_state: &ClientState, options: &OutputOptions) -> Self {
+        ClientRow {
+            client,
+            available: options.round(client_state.available),
+            held: options.round(client_state.held),
+            total: options.round(client_state.total),
+            locked: (!options.status).then(|| client_state.status.is_locked()),
+            status: options.status.then_some(client_state.status),
+            fees: options.fees.then(|| options.round(client_state.fees)),
+            pending: options.pending.then(|| options.round(client_state.pending)),
+            overdrawn: options.overdrawn.then(|| options.round(client_state.overdrawn)),
+        }
     }
-    Ok(clients_state)
 }
 
 /// Writes to source formated as a CSV.
@@ -29,21 +303,210 @@ pub fn csv_reader(from: impl Read) -> Result<HashMap<ClientId, ClientState>, Box
 /// 
 /// # Arguments
 /// 
+/// `clients_state` - state of each client, e.g. `Engine::clients_state`
+/// `to` - destination that should implement the Write trait
+/// `options` - optional columns to emit, order and filter of the rows, precision and header
+pub fn csv_writer(
+    clients_state: impl IntoIterator<Item = (ClientId, ClientState)>,
+    to: impl Write,
+    options: OutputOptions,
+) -> Result<(), std::io::Error> {
+    let mut writer = csv::Writer::from_writer(to);
+    if options.header {
+        writer.write_field("client")?;
+        writer.write_field("available")?;
+        writer.write_field("held")?;
+        writer.write_field("total")?;
+        writer.write_field(if options.status { "status" } else { "locked" })?;
+        if options.fees {
+            writer.write_field("fees")?;
+        }
+        if options.pending {
+            writer.write_field("pending")?;
+        }
+        if options.overdrawn {
+            writer.write_field("overdrawn")?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+    }
+    let amount = |amount: f64| format!("{:.*}", options.precision, amount);
+    for (client_id, client_state) in options.rows(clients_state) {
+        writer.write_field(client_id.to_string())?;
+        writer.write_field(amount(client_state.available))?;
+        writer.write_field(amount(client_state.held))?;
+        writer.write_field(amount(client_state.total))?;
+        if options.status {
+            writer.write_field(client_state.status.as_str())?;
+        } else {
+            writer.write_field(client_state.status.is_locked().to_string())?;
+        }
+        if options.fees {
+            writer.write_field(amount(client_state.fees))?;
+        }
+        if options.pending {
+            writer.write_field(amount(client_state.pending))?;
+        }
+        if options.overdrawn {
+            writer.write_field(amount(client_state.overdrawn))?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+    }
+    writer.flush()
+}
+
+/// Writes the clients' final state as a JSON array.
+/// Each object represents a client's final state, with the fields of the `csv_writer` columns.
+/// 
+/// # Arguments
+/// 
+/// `clients_state` - state of each client, e.g. `Engine::clients_state`
+/// `to` - destination that should implement the Write trait
+/// `options` - optional fields to emit, order and filter of the clients, precision of the amounts
+pub fn json_writer(
+    clients_state: impl IntoIterator<Item = (ClientId, ClientState)>,
+    to: impl Write,
+    options: OutputOptions,
+) -> Result<(), Box<dyn Error>> {
+    let rows: Vec<_> = options
+        .rows(clients_state)
+        .iter()
+        .map(|(client_id, client_state)| ClientRow::new(*client_id, client_state, &options))
+        .collect();
+    let mut stream = BufWriter::new(to);
+    serde_json::to_writer_pretty(&mut stream, &rows)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes the clients' final state as JSON Lines.
+/// Each line is an object representing a client's final state, with the fields of the `csv_writer` columns.
+/// 
+/// # Arguments
+/// 
+/// `clients_state` - state of each client, e.g. `Engine::clients_state`
+/// `to` - destination that should implement the Write trait
+/// `options` - optional fields to emit, order and filter of the clients, precision of the amounts
+pub fn json_lines_writer(
+    clients_state: impl IntoIterator<Item = (ClientId, ClientState)>,
+    to: impl Write,
+    options: OutputOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut stream = BufWriter::new(to);
+    for (client_id, client_state) in options.rows(clients_state) {
+        serde_json::to_writer(&mut stream, &ClientRow::new(client_id, &client_state, &options))?;
+        stream.write_all(b"\n")?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes daily statements formated as a CSV.
+/// Each statement is written as an `opening` line, a line per applied transaction with the movements it caused,
+/// and a `closing` line with the fees and disputes of the day.
+/// 
+/// # Arguments
+/// 
 /// `to` - destination that should implement the Write trait
-pub fn csv_writer(clients_state: HashMap<ClientId, ClientState>, to: impl Write) -> Result<(), std::io::Error> {
+pub fn statements_csv_writer(statements: &[Statement], to: impl Write) -> Result<(), std::io::Error> {
     let mut stream = BufWriter::new(to);
-    stream.write(b"client,available,held,total,locked")?;
-    for (client_id, client_state) in clients_state {
+    stream.write_all(b"day,client,entry,tx,timestamp,available,held,total,fees,disputes_opened,disputes_closed")?;
+    for statement in statements {
+        let day = statement.day.map(|day| day.to_string()).unwrap_or_default();
+        let (opening, closing) = (&statement.opening, &statement.closing);
+        write!(
+            stream,
+            "\n{},{},opening,,,{:.4},{:.4},{:.4},,,",
+            day, statement.client, opening.available, opening.held, opening.total,
+        )?;
+        for line in &statement.lines {
+            write!(
+                stream,
+                "\n{},{},{},{},{},{:.4},{:.4},{:.4},{:.4},,",
+                day,
+                statement.client,
+                line.kind,
+                line.tx,
+                line.timestamp.map(|timestamp| timestamp.to_string()).unwrap_or_default(),
+                line.available,
+                line.held,
+                line.total,
+                line.fees,
+            )?;
+        }
         write!(
             stream,
-            "\n{},{:.4},{:.4},{:.4},{}",
-            client_id,
-            client_state.available,
-            client_state.held,
-            client_state.total,
-            client_state.locked
+            "\n{},{},closing,,,{:.4},{:.4},{:.4},{:.4},{},{}",
+            day,
+            statement.client,
+            closing.available,
+            closing.held,
+            closing.total,
+            statement.fees,
+            statement.disputes_opened,
+            statement.disputes_closed,
         )?;
     }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes daily statements as a JSON array.
+/// 
+/// # Arguments
+/// 
+/// `to` - destination that should implement the Write trait
+pub fn statements_json_writer(statements: &[Statement], to: impl Write) -> Result<(), Box<dyn Error>> {
+    let mut stream = BufWriter::new(to);
+    serde_json::to_writer_pretty(&mut stream, statements)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes a trial balance formated as a CSV.
+/// Each line holds the debits and credits of an account, followed by a `total` line.
+/// 
+/// # Arguments
+/// 
+/// `to` - destination that should implement the Write trait
+pub fn trial_balance_writer(trial_balance: &TrialBalance, to: impl Write) -> Result<(), std::io::Error> {
+    let mut stream = BufWriter::new(to);
+    stream.write_all(b"account,debit,credit")?;
+    for line in &trial_balance.lines {
+        write!(stream, "\n{},{:.4},{:.4}", line.account, line.debit, line.credit)?;
+    }
+    write!(stream, "\ntotal,{:.4},{:.4}", trial_balance.debits, trial_balance.credits)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes transactions formated as a CSV, in the format read by `csv_reader`.
+/// Writing stops once the transactions are exhausted or `max_bytes` have been written.
+/// 
+/// # Arguments
+/// 
+/// `transactions` - transactions to write, e.g. from a `Generator`
+/// `max_bytes` - optional size of the output, reached within a row
+/// `to` - destination that should implement the Write trait
+pub fn transactions_writer(
+    transactions: impl IntoIterator<Item = Transaction>,
+    max_bytes: Option<u64>,
+    to: impl Write,
+) -> Result<(), std::io::Error> {
+    let mut stream = BufWriter::new(to);
+    let header = "type,client,tx,amount,operator";
+    stream.write_all(header.as_bytes())?;
+    let mut written = header.len() as u64;
+    for transaction in transactions {
+        if max_bytes.is_some_and(|max_bytes| written >= max_bytes) {
+            break;
+        }
+        let amount = transaction.amount.map(|amount| format!("{amount:.4}")).unwrap_or_default();
+        let operator = transaction.operator.map(|operator| operator.to_string()).unwrap_or_default();
+        let row = format!("\n{},{},{},{},{}", transaction.tx_type.as_str(), transaction.client, transaction.tx, amount, operator);
+        stream.write_all(row.as_bytes())?;
+        written += row.len() as u64;
+    }
+    stream.flush()?;
     Ok(())
 }
 
@@ -53,6 +516,31 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
+    use crate::admin::{AdminAction, AdminEntry};
+    use crate::engine::{parse_timestamp, ClearingState, DeadlinePolicy, DisputeState, EngineConfig};
+    use crate::export::{beancount_writer, ledger_writer};
+    use crate::fees::{FeeEntry, FeeKind};
+    use crate::generate::{Generator, GeneratorConfig};
+    use crate::interest::{InterestEntry, InterestPolicy};
+    use crate::ledger::{Account, ClientBalance};
+    use crate::limits::{BreachReason, LimitAction};
+    use crate::parallel::{parallel_feed, DEFAULT_BLOCK_SIZE};
+    use crate::reorder::ReorderBuffer;
+    use crate::status::AccountStatus;
+
+    /// Feeds a CSV to an engine configured with the invariant checker on, failing on any violation.
+    fn audited_feed(input: &[u8], engine: &mut Engine) {
+        assert!(engine.config.audit, "the invariant checker must be on in tests");
+        csv_feed(input, engine).unwrap();
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    /// Reads a CSV like `csv_reader`, with the invariant checker on.
+    fn audited_reader(input: &[u8]) -> HashMap<ClientId, ClientState> {
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+        engine.clients_state
+    }
 
     #[test]
     fn deposits_increase_total_and_available_funds() {
@@ -65,11 +553,14 @@ mod tests {
                 available: 2.0,
                 held: 0.0,
                 total: 2.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -85,11 +576,14 @@ mod tests {
                 available: 0.0,
                 held: 0.0,
                 total: 0.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -105,11 +599,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -125,11 +622,14 @@ mod tests {
                 available: 0.0,
                 held: 1.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -145,11 +645,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -166,7 +669,10 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
         expected_clients_state.insert(
@@ -175,11 +681,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -195,11 +704,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -215,11 +727,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -235,11 +750,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -257,11 +775,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -278,11 +799,14 @@ mod tests {
                 available: 0.0,
                 held: 0.0,
                 total: 0.0,
-                locked: true,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -298,11 +822,14 @@ mod tests {
                 available: 0.0,
                 held: 0.0,
                 total: 0.0,
-                locked: true,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -318,11 +845,14 @@ mod tests {
                 available: 0.0,
                 held: 1.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -338,11 +868,14 @@ mod tests {
                 available: 1.0,
                 held: 0.0,
                 total: 1.0,
-                locked: false,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
@@ -360,40 +893,1764 @@ mod tests {
                 available: 0.0,
                 held: 0.0,
                 total: 0.0,
-                locked: true,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
             },
         );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
         assert_eq!(clients_state, expected_clients_state);
     }
 
     #[test]
-    fn output_is_correctly_formated() {
-        let input = "type,client,tx,amount
-deposit,1,1,1.0
-deposit,2,2,2.0
-deposit,1,3,2.0
-withdrawal,1,4,1.5
-withdrawal,2,5,3.0"
-            .as_bytes();
+    fn refund_decreases_available_and_total_funds() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\nrefund,1,1,0.5\nrefund,1,1,0.5".as_bytes();
 
-        let expected_lines = [
-            "client,available,held,total,locked",
-            "1,1.5000,0.0000,1.5000,false",
-            "2,2.0000,0.0000,2.0000,false"
-        ];
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
 
-        let clients_state = csv_reader(input).unwrap();
+        let clients_state = audited_reader(input);
 
-        let mut utf8_output = Vec::new();
-        csv_writer(clients_state, &mut utf8_output).unwrap();
+        assert_eq!(clients_state, expected_clients_state);
+    }
 
-        let str_output = String::from_utf8(utf8_output).unwrap();
-        
-        for expected_line in expected_lines {
-            assert!(str_output.contains(expected_line));
-        }
+    #[test]
+    fn refund_exceeding_original_deposit_is_ignored() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,5.0\nrefund,1,1,0.75\nrefund,1,1,0.5".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 5.25,
+                held: 0.0,
+                total: 5.25,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn refund_on_withdrawal_or_other_client_transaction_is_ignored() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,1.0\ndeposit,2,3,1.0\nrefund,1,2,1.0\nrefund,1,3,1.0".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+        expected_clients_state.insert(
+            2,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn non_positive_refund_is_ignored() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\nrefund,1,1,-5.0\nrefund,1,1,0.0\ndeposit,1,2,-1.0\nwithdrawal,1,3,-1.0"
+            .as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 0.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn chargeback_after_partial_refund_only_debits_remaining_amount() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,1,2,1.0\nrefund,1,1,1.5\ndispute,1,1,\nchargeback,1,1,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn dispute_on_already_disputed_transaction_is_ignored() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\ndispute,1,1,\ndispute,1,1,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 1.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn transaction_reusing_a_tx_id_is_ignored() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,5.0\ndispute,1,1,\nwithdrawal,1,1,1.0\ndeposit,1,1,2.0\nresolve,1,1,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 5.0,
+                held: 0.0,
+                total: 5.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn representment_reverses_chargeback() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\nchargeback,1,1,\nrepresentment,1,1,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn representment_unlocks_account_when_configured() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\nchargeback,1,1,\nrepresentment,1,1,\ndeposit,1,2,1.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, unlock_on_reversal: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 0.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn chargeback_cycle_leaves_closed_account_untouched() {
+        let run = |input: &str| {
+            let mut engine = Engine::new(EngineConfig { audit: true, unlock_on_reversal: true, ..Default::default() });
+            audited_feed(input.as_bytes(), &mut engine);
+            engine.clients_state[&1]
+        };
+        let closed = ClientState { status: AccountStatus::Closed, ..Default::default() };
+
+        let representment = "type,client,tx,amount,operator
+deposit,1,1,5.0,
+deposit,1,2,3.0,
+dispute,1,1,,
+chargeback,1,1,,
+close,1,3,,7
+representment,1,1,,";
+        assert_eq!(run(representment), closed);
+
+        let second_chargeback = "type,client,tx,amount,operator
+deposit,1,1,5.0,
+deposit,1,2,3.0,
+dispute,1,1,,
+chargeback,1,1,,
+representment,1,1,,
+close,1,3,,7
+chargeback,1,1,,
+arbitration,1,1,,";
+        assert_eq!(run(second_chargeback), closed);
+    }
+
+    #[test]
+    fn second_chargeback_is_reversed_by_arbitration() {
+        let fees = "kind,model,min_amount,value\nchargeback,flat,,0.25".as_bytes();
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\ndispute,1,1,\nchargeback,1,1,\nrepresentment,1,1,\nchargeback,1,1,\narbitration,1,1,\narbitration,1,1,".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule: fee_schedule_reader(fees).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.5,
+                held: 0.0,
+                total: 1.5,
+                status: AccountStatus::Locked,
+                fees: 0.5,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().dispute_state, DisputeState::Arbitrated);
+    }
+
+    #[test]
+    fn representment_and_arbitration_out_of_cycle_are_ignored() {
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,1.0\nrepresentment,1,1,\ndispute,1,1,\narbitration,1,1,\nrepresentment,1,1,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 0.0,
+                held: 1.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn unlock_reactivates_locked_account() {
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,2.0,\ndeposit,1,2,1.0,\ndispute,1,1,,\nchargeback,1,1,,\nunlock,1,3,,7\ndeposit,1,4,1.0,".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 0.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(
+            engine.admin_history,
+            vec![AdminEntry { action: AdminAction::Unlock, operator: 7, client: 1, tx: 3, payout: 0.0 }]
+        );
+    }
+
+    #[test]
+    fn freeze_blocks_withdrawals_but_allows_deposits() {
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,2.0,\nfreeze,1,2,,7\nwithdrawal,1,3,1.0,\ndeposit,1,4,1.0,".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 3.0,
+                held: 0.0,
+                total: 3.0,
+                status: AccountStatus::Frozen,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn close_pays_out_and_retires_account() {
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,2.0,\nclose,1,2,,7\ndeposit,1,3,1.0,\nunlock,1,4,,7".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 0.0,
+                held: 0.0,
+                total: 0.0,
+                status: AccountStatus::Closed,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(
+            engine.admin_history,
+            vec![AdminEntry { action: AdminAction::Close, operator: 7, client: 1, tx: 2, payout: 2.0 }]
+        );
+    }
+
+    #[test]
+    fn close_with_held_funds_is_refused() {
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,2.0,\ndispute,1,1,,\nclose,1,2,,7".as_bytes();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 0.0,
+                held: 2.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        let clients_state = audited_reader(input);
+
+        assert_eq!(clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn admin_transaction_without_operator_is_ignored() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\nfreeze,1,2,".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].status, AccountStatus::Active);
+        assert!(engine.admin_history.is_empty());
+    }
+
+    #[test]
+    fn permission_matrix_lets_locked_account_resolve_disputes() {
+        let permissions = "status,operation,allowed\nlocked,resolve,true".as_bytes();
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndispute,1,1,\ndispute,1,2,\nchargeback,1,1,\nresolve,1,2,\ndeposit,1,3,1.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, permissions: permissions_reader(permissions).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 0.0,
+                total: 2.0,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn permission_matrix_can_deny_deposits_on_frozen_account() {
+        let permissions = "status,operation,allowed\nfrozen,deposit,false".as_bytes();
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,1.0,\nfreeze,1,2,,7\ndeposit,1,3,1.0,".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, permissions: permissions_reader(permissions).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 1.0);
+    }
+
+    #[test]
+    fn withdrawal_above_single_amount_limit_is_rejected() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action\n,,withdrawal,100,,,,reject".as_bytes();
+        let input = "type,client,tx,amount\ndeposit,1,1,500.0\nwithdrawal,1,2,150.0\nwithdrawal,1,3,100.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, "client,tier".as_bytes()).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 400.0);
+        assert_eq!(engine.limit_breaches.len(), 1);
+        assert_eq!(
+            engine.limit_breaches[0].to_string(),
+            "transaction 2 of client 1 rejected: amount 150.0000 exceeds the single transaction limit of 100.0000"
+        );
+    }
+
+    #[test]
+    fn daily_total_limit_resets_the_next_day() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action\n,,withdrawal,,daily,100,,reject".as_bytes();
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,500.0,0
+withdrawal,1,2,60.0,3600
+withdrawal,1,3,60.0,7200
+withdrawal,1,4,40.0,10800
+withdrawal,1,5,60.0,86400"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, "client,tier".as_bytes()).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 340.0);
+        assert_eq!(engine.limit_breaches.len(), 1);
+        assert_eq!(engine.limit_breaches[0].tx, 3);
+        assert_eq!(engine.limit_breaches[0].reason, BreachReason::MaxTotal { limit: 100.0, total: 120.0 });
+    }
+
+    #[test]
+    fn rolling_count_limit_flags_without_rejecting() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action\n,,deposit,,60,,2,flag".as_bytes();
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+deposit,1,2,1.0,30
+deposit,1,3,1.0,59
+deposit,1,4,1.0,90"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, "client,tier".as_bytes()).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 4.0);
+        assert_eq!(engine.limit_breaches.len(), 1);
+        assert_eq!(engine.limit_breaches[0].tx, 3);
+        assert_eq!(engine.limit_breaches[0].action, LimitAction::Flag);
+    }
+
+    #[test]
+    fn huge_rolling_window_saturates_instead_of_overflowing() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action
+,,deposit,,18446744073709551615,,2,reject"
+            .as_bytes();
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+deposit,1,2,1.0,10
+deposit,1,3,1.0,1000"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, "client,tier".as_bytes()).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 2.0);
+        assert_eq!(engine.limit_breaches.len(), 1);
+        assert_eq!(engine.limit_breaches[0].tx, 3);
+    }
+
+    #[test]
+    fn client_limits_override_tier_limits() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action
+,gold,withdrawal,1000,,,,reject
+,,withdrawal,10,,,,reject
+2,,withdrawal,50,,,,reject"
+            .as_bytes();
+        let tiers = "client,tier\n1,gold\n2,gold".as_bytes();
+        let input = "type,client,tx,amount
+deposit,1,1,500.0
+deposit,2,2,500.0
+deposit,3,3,500.0
+withdrawal,1,4,100.0
+withdrawal,2,5,100.0
+withdrawal,3,6,100.0"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, tiers).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 400.0);
+        assert_eq!(engine.clients_state[&2].total, 500.0);
+        assert_eq!(engine.clients_state[&3].total, 500.0);
+    }
+
+    #[test]
+    fn limits_are_checked_after_status_and_only_for_deposits_and_withdrawals() {
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action\n,,withdrawal,100,,,,reject".as_bytes();
+        let input = "type,client,tx,amount,operator
+deposit,1,1,500.0,
+freeze,1,2,,7
+withdrawal,1,3,150.0,"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, limits: limits_reader(limits, "client,tier".as_bytes()).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 500.0);
+        assert!(engine.limit_breaches.is_empty());
+
+        let limits = "client,tier,operation,max_amount,window,max_total,max_count,action\n,,dispute,100,,,,reject".as_bytes();
+        let err = limits_reader(limits, "client,tier".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("limits only apply to deposit and withdrawal, not dispute"), "{err}");
+    }
+
+    #[test]
+    fn timestamps_are_read_as_rfc3339_or_epoch() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,2022-03-01T10:00:00Z
+deposit,1,2,1.0,1646132400
+deposit,1,3,1.0,2022-03-01T12:30:00+01:00
+deposit,1,4,1.0,"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().timestamp, Some(1646128800));
+        assert_eq!(engine.transaction_history.get(&(1, 2)).unwrap().timestamp, Some(1646132400));
+        assert_eq!(engine.transaction_history.get(&(1, 3)).unwrap().timestamp, Some(1646134200));
+        assert_eq!(engine.transaction_history.get(&(1, 4)).unwrap().timestamp, None);
+    }
+
+    #[test]
+    fn invalid_timestamp_is_an_error() {
+        let input = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,yesterday".as_bytes();
+
+        assert!(csv_reader(input).is_err());
+    }
+
+    #[test]
+    fn transaction_going_back_in_time_is_ignored() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,100
+deposit,2,2,1.0,50
+deposit,1,3,1.0,99
+deposit,1,4,1.0,100"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].total, 2.0);
+        assert_eq!(engine.clients_state[&2].total, 1.0);
+        assert!(!engine.transaction_history.contains_key(&(1, 3)));
+    }
+
+    #[test]
+    fn reorder_buffer_restores_order_within_window() {
+        let input = "type,client,tx,amount,timestamp\nwithdrawal,1,2,1.0,20\ndeposit,1,1,2.0,10".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        csv_feed_reordered(input, &mut engine, &mut ReorderBuffer::new(15, 100)).unwrap();
+
+        assert_eq!(engine.clients_state[&1].total, 1.0);
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn reorder_buffer_lets_transaction_later_than_window_through() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,10
+deposit,1,2,1.0,20
+withdrawal,1,3,1.0,30
+deposit,1,4,5.0,12"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        csv_feed_reordered(input, &mut engine, &mut ReorderBuffer::new(5, 100)).unwrap();
+
+        // Deposit 4 arrived after deposit 2 was handled, the engine ignores it as it goes back in time
+        assert_eq!(engine.clients_state[&1].total, 1.0);
+        assert!(!engine.transaction_history.contains_key(&(1, 4)));
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn reorder_buffer_parks_dispute_until_its_deposit_arrives() {
+        let input = "type,client,tx,amount,seq
+dispute,1,1,,1
+deposit,1,2,2.0,2
+deposit,1,1,1.0,3"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        let mut buffer = ReorderBuffer::new(2, 100);
+        csv_feed_reordered(input, &mut engine, &mut buffer).unwrap();
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 1.0,
+                total: 3.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert!(buffer.is_empty());
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn reorder_buffer_drops_parked_dispute_once_window_expires() {
+        let input = "type,client,tx,amount,seq
+dispute,1,1,,1
+deposit,1,2,2.0,5
+deposit,1,1,1.0,9"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        csv_feed_reordered(input, &mut engine, &mut ReorderBuffer::new(3, 100)).unwrap();
+
+        assert_eq!(engine.clients_state[&1].available, 3.0);
+        assert_eq!(engine.clients_state[&1].held, 0.0);
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn dispute_older_than_window_is_ignored() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+deposit,1,2,1.0,50
+dispute,1,1,,101
+dispute,1,2,,101"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, dispute_window: Some(100), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].held, 1.0);
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().dispute_state, DisputeState::Undisputed);
+    }
+
+    #[test]
+    fn open_dispute_is_resolved_at_deadline() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+dispute,1,1,,10
+deposit,2,2,1.0,59
+deposit,2,3,1.0,60"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, dispute_deadline: Some(50), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 1.0);
+        assert_eq!(engine.clients_state[&1].held, 0.0);
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().dispute_state, DisputeState::Undisputed);
+    }
+
+    #[test]
+    fn open_dispute_is_settled_at_deadline_on_locked_account() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+deposit,1,2,2.0,0
+dispute,1,1,,10
+dispute,1,2,,20
+chargeback,1,2,,30"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, dispute_deadline: Some(50), ..Default::default() });
+        audited_feed(input, &mut engine);
+        assert_eq!(engine.clients_state[&1].status, AccountStatus::Locked);
+
+        engine.advance_time(100);
+
+        assert_eq!(engine.clients_state[&1].available, 1.0);
+        assert_eq!(engine.clients_state[&1].held, 0.0);
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().dispute_state, DisputeState::Undisputed);
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn dispute_deadline_saturates_instead_of_overflowing() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+dispute,1,1,,10
+deposit,2,2,1.0,18446744073709551615"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, dispute_deadline: Some(u64::MAX), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].held, 0.0);
+        assert_eq!(engine.transaction_history.get(&(1, 1)).unwrap().dispute_state, DisputeState::Undisputed);
+    }
+
+    #[test]
+    fn open_dispute_is_charged_back_at_deadline_when_configured() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,0
+deposit,1,2,1.0,0
+dispute,1,1,,10
+dispute,1,2,,20
+resolve,1,2,,30"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig {
+            audit: true,
+            dispute_deadline: Some(50),
+            deadline_policy: DeadlinePolicy::Chargeback,
+            ..Default::default()
+        });
+        audited_feed(input, &mut engine);
+        assert_eq!(engine.clients_state[&1].status, AccountStatus::Active);
+
+        engine.advance_time(100);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Locked,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn pending_deposit_is_only_available_once_cleared() {
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,1.0
+dispute,1,1,
+clear,1,1,
+withdrawal,1,3,1.0"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, pending_deposits: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn pending_deposit_clears_after_delay() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,2.0,0
+deposit,1,2,3.0,50
+withdrawal,1,3,1.0,100"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, pending_deposits: true, clearing_delay: Some(100), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 4.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 3.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn clearing_delay_saturates_instead_of_overflowing() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,2.0,10
+deposit,2,2,1.0,18446744073709551615"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, pending_deposits: true, clearing_delay: Some(u64::MAX), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 2.0);
+        assert_eq!(engine.clients_state[&1].pending, 0.0);
+    }
+
+    #[test]
+    fn return_reverses_uncleared_deposit_only() {
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+deposit,1,2,3.0
+clear,1,1,
+return,1,1,
+return,1,2,
+clear,1,2,"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, pending_deposits: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 2.0,
+                held: 0.0,
+                total: 2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(engine.transaction_history.get(&(1, 2)).unwrap().clearing_state, ClearingState::Returned);
+    }
+
+    #[test]
+    fn withdrawal_draws_on_credit_line() {
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,5.0
+withdrawal,1,3,2.0
+deposit,1,4,1.0"
+            .as_bytes();
+
+        let credit_limits = credit_limits_reader("client,credit_limit\n1,4.0".as_bytes()).unwrap();
+        let mut engine = Engine::new(EngineConfig { audit: true, credit_limits, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: -2.0,
+                held: 0.0,
+                total: -2.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 2.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn overdraft_fee_is_charged_on_drawn_part() {
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,4.0
+withdrawal,1,3,0.5"
+            .as_bytes();
+
+        let fee_schedule = fee_schedule_reader("kind,model,min_amount,value\noverdraft,percentage,,10".as_bytes()).unwrap();
+        let credit_limits = credit_limits_reader("client,credit_limit\n1,3.0".as_bytes()).unwrap();
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule, credit_limits, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: -2.75,
+                held: 0.0,
+                total: -2.75,
+                status: AccountStatus::Active,
+                fees: 0.25,
+                pending: 0.0,
+                overdrawn: 2.75,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(
+            engine.fee_history[&1],
+            vec![
+                FeeEntry { kind: FeeKind::Overdraft, tx: 2, amount: 0.2 },
+                FeeEntry { kind: FeeKind::Overdraft, tx: 3, amount: 0.05 },
+            ]
+        );
+    }
+
+    #[test]
+    fn eod_rows_post_interest_on_available_balances() {
+        let input = "type,client,tx,amount
+deposit,1,1,1000.0
+deposit,2,2,5.0
+withdrawal,2,3,5.0
+eod,0,0,
+withdrawal,1,4,2.0
+eod,0,0,
+dispute,1,4294967295,"
+            .as_bytes();
+
+        let interest = InterestPolicy { rate: 73.0, cutoff: 0, ..Default::default() };
+        let mut engine = Engine::new(EngineConfig { audit: true, interest: Some(interest), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1002.0,
+                held: 0.0,
+                total: 1002.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+        expected_clients_state.insert(
+            2,
+            ClientState {
+                available: 0.0,
+                held: 0.0,
+                total: 0.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(
+            engine.interest_history[&1],
+            vec![
+                InterestEntry { tx: u32::MAX, day: None, amount: 2.0 },
+                InterestEntry { tx: u32::MAX - 1, day: None, amount: 2.0 },
+            ]
+        );
+        assert!(!engine.interest_history.contains_key(&2));
+    }
+
+    #[test]
+    fn interest_postings_skip_the_transaction_ids_of_the_client() {
+        let input = "type,client,tx,amount
+deposit,1,4294967295,1000.0
+eod,0,0,
+dispute,1,4294967295,"
+            .as_bytes();
+
+        let interest = InterestPolicy { rate: 73.0, cutoff: 0, ..Default::default() };
+        let mut engine = Engine::new(EngineConfig { audit: true, interest: Some(interest), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 2.0);
+        assert_eq!(engine.clients_state[&1].held, 1000.0);
+        assert_eq!(engine.interest_history[&1], vec![InterestEntry { tx: u32::MAX - 1, day: None, amount: 2.0 }]);
+    }
+
+    #[test]
+    fn deposits_reusing_the_id_of_an_interest_posting_are_applied() {
+        let input = "type,client,tx,amount
+deposit,1,1,1000.0
+deposit,2,2,1.0
+withdrawal,2,3,6.0
+eod,0,0,
+deposit,1,4294967295,998.0
+deposit,2,4294967294,10.0
+eod,0,0,"
+            .as_bytes();
+
+        let interest = InterestPolicy { rate: 73.0, overdraft_rate: 73.0, cutoff: 0 };
+        let mut engine = Engine::new(EngineConfig {
+            audit: true,
+            interest: Some(interest),
+            credit_limits: HashMap::from([(2, 10.0)]),
+            ..Default::default()
+        });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 2004.0);
+        assert_eq!(engine.transaction_history.get(&(1, u32::MAX)).map(|summary| summary.amount), Some(998.0));
+        assert_eq!(engine.transaction_history.get(&(2, u32::MAX - 1)).map(|summary| summary.amount), Some(10.0));
+        assert_eq!(
+            engine.interest_history[&1],
+            vec![
+                InterestEntry { tx: u32::MAX, day: None, amount: 2.0 },
+                InterestEntry { tx: u32::MAX - 2, day: None, amount: 4.0 },
+            ]
+        );
+        assert_eq!(engine.interest_history[&2][0], InterestEntry { tx: u32::MAX - 1, day: None, amount: -0.01 });
+    }
+
+    #[test]
+    fn interest_accrues_at_each_business_day_boundary() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1000.0,2022-03-01T10:00:00Z
+withdrawal,1,2,2.0,2022-03-02T10:00:00Z
+deposit,1,3,1.0,2022-03-03T10:00:00Z"
+            .as_bytes();
+
+        let interest = InterestPolicy { rate: 73.0, cutoff: 12 * 3600, ..Default::default() };
+        let mut engine = Engine::new(EngineConfig { audit: true, interest: Some(interest), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 1003.0);
+        let first_day = parse_timestamp("2022-03-01T00:00:00Z").unwrap() / 86_400;
+        assert_eq!(
+            engine.interest_history[&1],
+            vec![
+                InterestEntry { tx: u32::MAX, day: Some(first_day - 1), amount: 2.0 },
+                InterestEntry { tx: u32::MAX - 1, day: Some(first_day), amount: 2.0 },
+            ]
+        );
+        assert!(!engine.transaction_history.contains_key(&(1, u32::MAX - 1)));
+    }
+
+    #[test]
+    fn overdraft_interest_is_charged_daily_on_overdrawn_balance() {
+        let input = "type,client,tx,amount
+deposit,1,1,100.0
+withdrawal,1,2,300.0
+eod,0,0,
+eod,0,0,
+deposit,1,3,300.0
+eod,0,0,"
+            .as_bytes();
+
+        let interest = InterestPolicy { overdraft_rate: 73.0, ..Default::default() };
+        let credit_limits = HashMap::from([(1, 500.0)]);
+        let mut engine = Engine::new(EngineConfig { audit: true, interest: Some(interest), credit_limits, ledger: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(
+            engine.interest_history[&1],
+            vec![
+                InterestEntry { tx: u32::MAX, day: None, amount: -0.4 },
+                InterestEntry { tx: u32::MAX - 1, day: None, amount: -0.4008 },
+            ]
+        );
+        let client_state = engine.clients_state[&1];
+        assert_eq!((client_state.available, client_state.overdrawn), (-200.0 - 0.4 - 0.4008 + 300.0, 0.0));
+        assert_eq!(engine.ledger.balance(Account::InterestIncome), -0.4 - 0.4008);
+        assert!(engine.ledger.trial_balance().is_balanced());
+    }
+
+    #[test]
+    fn withdrawal_charges_tiered_fee() {
+        let fees = "kind,model,min_amount,value\nwithdrawal,flat,,0.5\nwithdrawal,percentage,100,1.0".as_bytes();
+        let input = "type,client,tx,amount\ndeposit,1,1,300.0\nwithdrawal,1,2,10.0\nwithdrawal,1,3,200.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule: fee_schedule_reader(fees).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 87.5,
+                held: 0.0,
+                total: 87.5,
+                status: AccountStatus::Active,
+                fees: 2.5,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert_eq!(
+            engine.fee_history[&1],
+            vec![
+                FeeEntry { kind: FeeKind::Withdrawal, tx: 2, amount: 0.5 },
+                FeeEntry { kind: FeeKind::Withdrawal, tx: 3, amount: 2.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn conversion_fee_is_charged_on_transactions_in_another_currency() {
+        let fees = "kind,model,min_amount,value\nwithdrawal,flat,,0.5\nconversion,percentage,,1.0".as_bytes();
+        let input = "type,client,tx,amount,currency
+deposit,1,1,300.0,EUR
+deposit,1,2,100.0,USD
+withdrawal,1,3,100.0,
+withdrawal,1,4,200.0,GBP
+withdrawal,1,5,93.0,EUR"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig {
+            audit: true,
+            fee_schedule: fee_schedule_reader(fees).unwrap(),
+            currency: Some("USD".to_string()),
+            ..Default::default()
+        });
+        audited_feed(input, &mut engine);
+
+        assert_eq!(engine.clients_state[&1].available, 94.0);
+        assert_eq!(engine.clients_state[&1].fees, 6.0);
+        assert_eq!(
+            engine.fee_history[&1],
+            vec![
+                FeeEntry { kind: FeeKind::Conversion, tx: 1, amount: 3.0 },
+                FeeEntry { kind: FeeKind::Withdrawal, tx: 3, amount: 0.5 },
+                FeeEntry { kind: FeeKind::Withdrawal, tx: 4, amount: 0.5 },
+                FeeEntry { kind: FeeKind::Conversion, tx: 4, amount: 2.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn withdrawal_without_funds_for_fee_is_ignored() {
+        let fees = "kind,model,min_amount,value\nwithdrawal,flat,,0.5".as_bytes();
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,1.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule: fee_schedule_reader(fees).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 1.0,
+                held: 0.0,
+                total: 1.0,
+                status: AccountStatus::Active,
+                fees: 0.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+        assert!(!engine.fee_history.contains_key(&1));
+    }
+
+    #[test]
+    fn chargeback_charges_fee() {
+        let fees = "kind,model,min_amount,value\nchargeback,flat,,15.0".as_bytes();
+        let input =
+            "type,client,tx,amount\ndeposit,1,1,100.0\ndeposit,1,2,20.0\ndispute,1,1,\nchargeback,1,1,".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule: fee_schedule_reader(fees).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut expected_clients_state: HashMap<ClientId, ClientState> = HashMap::new();
+        expected_clients_state.insert(
+            1,
+            ClientState {
+                available: 5.0,
+                held: 0.0,
+                total: 5.0,
+                status: AccountStatus::Locked,
+                fees: 15.0,
+                pending: 0.0,
+                overdrawn: 0.0,
+            },
+        );
+
+        assert_eq!(engine.clients_state, expected_clients_state);
+    }
+
+    #[test]
+    fn fee_schedule_with_unknown_kind_is_rejected() {
+        let fees = "kind,model,min_amount,value\ntransfer,flat,,1.0".as_bytes();
+
+        assert!(fee_schedule_reader(fees).is_err());
+    }
+
+    #[test]
+    fn output_is_correctly_formated() {
+        let input = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0"
+            .as_bytes();
+
+        let expected_lines = [
+            "client,available,held,total,locked",
+            "1,1.5000,0.0000,1.5000,false",
+            "2,2.0000,0.0000,2.0000,false"
+        ];
+
+        let clients_state = audited_reader(input);
+
+        let mut utf8_output = Vec::new();
+        csv_writer(clients_state, &mut utf8_output, OutputOptions::default()).unwrap();
+
+        let str_output = String::from_utf8(utf8_output).unwrap();
+        
+        for expected_line in expected_lines {
+            assert!(str_output.contains(expected_line));
+        }
+    }
+
+    #[test]
+    fn output_shows_fees_when_asked() {
+        let fees = "kind,model,min_amount,value
+withdrawal,flat,,0.25".as_bytes();
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,1.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule: fee_schedule_reader(fees).unwrap(), ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut utf8_output = Vec::new();
+        csv_writer(engine.clients_state, &mut utf8_output, OutputOptions { fees: true, ..Default::default() }).unwrap();
+
+        let str_output = String::from_utf8(utf8_output).unwrap();
+
+        assert_eq!(str_output, "client,available,held,total,locked,fees\n1,0.7500,0.0000,0.7500,false,0.2500\n");
+    }
+
+    #[test]
+    fn output_shows_status_in_place_of_locked_when_asked() {
+        let input = "type,client,tx,amount,operator\ndeposit,1,1,2.0,\nfreeze,1,2,,7".as_bytes();
+
+        let clients_state = audited_reader(input);
+
+        let mut utf8_output = Vec::new();
+        csv_writer(clients_state, &mut utf8_output, OutputOptions { status: true, ..Default::default() }).unwrap();
+
+        let str_output = String::from_utf8(utf8_output).unwrap();
+
+        assert_eq!(str_output, "client,available,held,total,status\n1,2.0000,0.0000,2.0000,frozen\n");
+    }
+
+    #[test]
+    fn output_shows_pending_funds_when_asked() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0".as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, pending_deposits: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut utf8_output = Vec::new();
+        csv_writer(engine.clients_state, &mut utf8_output, OutputOptions { pending: true, ..Default::default() }).unwrap();
+
+        let str_output = String::from_utf8(utf8_output).unwrap();
+
+        assert_eq!(str_output, "client,available,held,total,locked,pending\n1,0.0000,0.0000,2.0000,false,2.0000\n");
+    }
+
+    #[test]
+    fn daily_close_produces_reconciled_statements() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,100.0,2022-03-01T10:00:00Z
+withdrawal,1,2,10.0,2022-03-01T11:00:00Z
+deposit,2,3,50.0,2022-03-01T12:00:00Z
+dispute,2,3,,2022-03-01T13:00:00Z
+resolve,2,3,,2022-03-02T09:00:00Z
+deposit,1,4,5.0,2022-03-02T10:00:00Z"
+            .as_bytes();
+
+        let fee_schedule = fee_schedule_reader("kind,model,min_amount,value\nwithdrawal,flat,,0.5".as_bytes()).unwrap();
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule, journal: true, ..Default::default() });
+        let mut close = DailyClose::new(0);
+        csv_feed_daily(input, &mut engine, &mut close).unwrap();
+
+        let first_day = parse_timestamp("2022-03-01T00:00:00Z").unwrap() / 86_400;
+        let summary: Vec<(ClientId, Option<u64>, usize, f64, usize, usize)> = close
+            .statements
+            .iter()
+            .map(|statement| {
+                (
+                    statement.client,
+                    statement.day,
+                    statement.lines.len(),
+                    statement.fees,
+                    statement.disputes_opened,
+                    statement.disputes_closed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Some(first_day), 2, 0.5, 0, 0),
+                (2, Some(first_day), 2, 0.0, 1, 0),
+                (1, Some(first_day + 1), 1, 0.0, 0, 0),
+                (2, Some(first_day + 1), 1, 0.0, 0, 1),
+            ]
+        );
+        assert_eq!(close.statements[0].closing.available, 89.5);
+        assert_eq!(close.statements[2].opening.available, 89.5);
+        assert!(close.statements.iter().all(Statement::reconciles));
+        for (client, client_state) in &engine.clients_state {
+            let net_movement: f64 = close
+                .statements
+                .iter()
+                .filter(|statement| statement.client == *client)
+                .map(Statement::net_movement)
+                .sum();
+            assert_eq!(net_movement, client_state.total);
+        }
+        assert!(engine.journal.is_empty());
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn daily_close_posts_interest_in_the_statement_of_its_day() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1000.0,2022-03-01T10:00:00Z
+deposit,1,2,1.0,2022-03-02T10:00:00Z"
+            .as_bytes();
+
+        let interest = InterestPolicy { rate: 73.0, cutoff: 0, ..Default::default() };
+        let mut engine = Engine::new(EngineConfig { audit: true, interest: Some(interest), journal: true, ..Default::default() });
+        let mut close = DailyClose::new(0);
+        csv_feed_daily(input, &mut engine, &mut close).unwrap();
+
+        let kinds: Vec<Vec<&str>> = close
+            .statements
+            .iter()
+            .map(|statement| statement.lines.iter().map(|line| line.kind.as_str()).collect())
+            .collect();
+        assert_eq!(kinds, vec![vec!["deposit", "interest"], vec!["deposit"]]);
+        assert_eq!(close.statements[0].closing.available, 1002.0);
+        assert!(close.statements.iter().all(Statement::reconciles));
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn statements_written_as_csv_and_json() {
+        let input = "type,client,tx,amount
+deposit,1,1,2.0
+eod,0,0,
+withdrawal,1,2,1.0"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, journal: true, ..Default::default() });
+        let mut close = DailyClose::new(0);
+        csv_feed_daily(input, &mut engine, &mut close).unwrap();
+
+        let mut output = Vec::new();
+        statements_csv_writer(&close.statements, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "day,client,entry,tx,timestamp,available,held,total,fees,disputes_opened,disputes_closed
+,1,opening,,,0.0000,0.0000,0.0000,,,
+,1,deposit,1,,2.0000,0.0000,2.0000,0.0000,,
+,1,closing,,,2.0000,0.0000,2.0000,0.0000,0,0
+,1,opening,,,2.0000,0.0000,2.0000,,,
+,1,withdrawal,2,,-1.0000,0.0000,-1.0000,0.0000,,
+,1,closing,,,1.0000,0.0000,1.0000,0.0000,0,0"
+        );
+
+        let mut output = Vec::new();
+        statements_json_writer(&close.statements, &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json[1]["lines"][0]["kind"], "withdrawal");
+        assert_eq!(json[1]["closing"]["available"], 1.0);
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn ledger_derives_client_balances_and_balances_trial() {
+        let input = "type,client,tx,amount,operator
+deposit,1,1,10.0,
+withdrawal,1,2,2.5,
+deposit,2,3,4.0,
+dispute,2,3,,
+chargeback,2,3,,
+deposit,3,4,1.0,
+close,3,5,,7"
+            .as_bytes();
+
+        let fee_schedule = fee_schedule_reader("kind,model,min_amount,value\nwithdrawal,flat,,0.5".as_bytes()).unwrap();
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule, ledger: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let trial_balance = engine.ledger.trial_balance();
+        assert!(trial_balance.is_balanced());
+        for (client, client_state) in &engine.clients_state {
+            let balance = |balance| -engine.ledger.balance(Account::Client(*client, balance));
+            assert_eq!(balance(ClientBalance::Available), client_state.available);
+            assert_eq!(balance(ClientBalance::Held), client_state.held);
+            assert_eq!(balance(ClientBalance::Pending), client_state.pending);
+        }
+        assert_eq!(engine.ledger.balance(Account::BankCash), 11.5);
+        assert_eq!(engine.ledger.balance(Account::Chargebacks), -4.0);
+        assert_eq!(engine.ledger.balance(Account::FeeIncome), -0.5);
+        assert_eq!(engine.ledger.entries.len(), 8);
+
+        let mut output = Vec::new();
+        trial_balance_writer(&trial_balance, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "account,debit,credit
+Assets:Cash,15.0000,3.5000
+Liabilities:Chargebacks,0.0000,4.0000
+Income:Fees,0.0000,0.5000
+Liabilities:Clients:1:Available,3.0000,10.0000
+Liabilities:Clients:2:Available,4.0000,4.0000
+Liabilities:Clients:2:Held,4.0000,4.0000
+Liabilities:Clients:3:Available,1.0000,1.0000
+total,27.0000,27.0000"
+        );
+    }
+
+    /// Sums the postings of a ledger-cli or beancount journal per account.
+    fn journal_balances(journal: &str) -> HashMap<String, f64> {
+        let mut balances = HashMap::new();
+        for posting in journal.lines().filter(|line| line.starts_with("  ")) {
+            let fields: Vec<&str> = posting.split_whitespace().collect();
+            *balances.entry(fields[0].to_string()).or_default() += fields[1].parse::<f64>().unwrap();
+        }
+        balances
+    }
+
+    #[test]
+    fn exported_journals_match_csv_writer() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,10.0,2022-03-01T10:00:00Z
+withdrawal,1,2,2.5,2022-03-01T11:00:00Z
+deposit,2,3,4.0,2022-03-02T10:00:00Z
+deposit,2,4,3.0,2022-03-02T11:00:00Z
+dispute,2,3,,2022-03-02T12:00:00Z
+dispute,1,1,,
+resolve,1,1,,"
+            .as_bytes();
+
+        let fee_schedule = fee_schedule_reader("kind,model,min_amount,value\nwithdrawal,flat,,0.5".as_bytes()).unwrap();
+        let mut engine = Engine::new(EngineConfig { audit: true, fee_schedule, ledger: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+
+        let mut ledger_journal = Vec::new();
+        ledger_writer(&engine.ledger.entries, "USD", &mut ledger_journal).unwrap();
+        let ledger_journal = String::from_utf8(ledger_journal).unwrap();
+        assert!(ledger_journal.starts_with("2022-03-01 deposit ; client:1, tx:1\n    Assets:Cash  10.0000 USD\n"));
+        let mut beancount = Vec::new();
+        beancount_writer(&engine.ledger.entries, "USD", &mut beancount).unwrap();
+        let beancount = String::from_utf8(beancount).unwrap();
+        assert!(beancount.contains("1970-01-01 open Liabilities:Clients:2:Held USD\n"));
+        assert!(beancount.contains("2022-03-02 * \"dispute\" \"client 2, tx 3\"\n"));
+
+        let mut output = Vec::new();
+        csv_writer(engine.clients_state, &mut output, OutputOptions::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        for journal in [ledger_journal, beancount] {
+            let balances = journal_balances(&journal);
+            let balance = |client: &str, name: &str| {
+                -balances.get(&format!("Liabilities:Clients:{client}:{name}")).copied().unwrap_or_default()
+            };
+            for row in output.lines().skip(1) {
+                let fields: Vec<&str> = row.split(',').collect();
+                let (available, held) = (balance(fields[0], "Available"), balance(fields[0], "Held"));
+                assert_eq!(available, fields[1].parse::<f64>().unwrap());
+                assert_eq!(held, fields[2].parse::<f64>().unwrap());
+                assert_eq!(available + held, fields[3].parse::<f64>().unwrap());
+            }
+            assert_eq!(balances["Assets:Cash"], 14.5);
+        }
+    }
+
+    #[test]
+    fn generated_transactions_round_trip_through_csv() {
+        let config = GeneratorConfig { clients: 10, dispute_ratio: 0.2, edge_case_ratio: 0.05, ..Default::default() };
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        for transaction in Generator::new(config).take(2000) {
+            engine.handle_transaction(&transaction);
+        }
+
+        let mut output = Vec::new();
+        transactions_writer(Generator::new(config).take(2000), None, &mut output).unwrap();
+        assert_eq!(csv_reader(output.as_slice()).unwrap(), engine.clients_state);
+        assert!(engine.ledger.trial_balance().is_balanced());
+
+        let mut output = Vec::new();
+        transactions_writer(Generator::new(config), Some(1000), &mut output).unwrap();
+        assert!((1000..1100).contains(&output.len()));
+        assert_eq!(engine.violations, vec![]);
+    }
+
+    #[test]
+    fn fast_path_reads_like_serde() {
+        let config = GeneratorConfig { clients: 10, dispute_ratio: 0.2, edge_case_ratio: 0.05, ..Default::default() };
+        let mut generated = Vec::new();
+        transactions_writer(Generator::new(config).take(2000), None, &mut generated).unwrap();
+        let unusual = "seq, amount,tx,type,client,note
+1,16,1,deposit,0x1,a
+2,1e1,2,deposit,+1,b
+3, 2.50 ,3,withdrawal,001,
+4,,2,dispute,1,
+5,-0,4,deposit,2,
+6,1.0,5,transfer,2,
+7,90071992547409930.1,6,deposit,3,";
+
+        for input in [generated.as_slice(), unusual.as_bytes()] {
+            assert_eq!(audited_reader(input), csv_reader_serde(input).unwrap());
+        }
+    }
+
+    #[test]
+    fn fast_path_returns_serde_errors() {
+        let inputs: [&[u8]; 6] = [
+            b"type,client,tx,amount\ndeposit,1,1,abc",
+            b"type,client,tx,amount\ndeposit,,1,1.0",
+            b"type,client,tx,amount\ndeposit,1,1,1.0,2.0",
+            b"type,client,tx,amount\ndeposit,70000,1,1.0",
+            b"type,client,tx,amount\ndeposit,1,1,1.0\n\xff,1,2,1.0",
+            b"type,tx,amount\ndeposit,1,1.0",
+        ];
+        for input in inputs {
+            assert_eq!(
+                csv_reader(input).unwrap_err().to_string(),
+                csv_reader_serde(input).unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_feed_matches_csv_feed() {
+        let config = GeneratorConfig { clients: 10, dispute_ratio: 0.2, edge_case_ratio: 0.05, ..Default::default() };
+        let mut input = Vec::new();
+        transactions_writer(Generator::new(config).take(2000), None, &mut input).unwrap();
+        let mut expected = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        csv_feed(input.as_slice(), &mut expected).unwrap();
+
+        for block_size in [1, 100, 4096, DEFAULT_BLOCK_SIZE] {
+            let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+            parallel_feed(&input, &mut engine, block_size).unwrap();
+            assert_eq!(engine.clients_state, expected.clients_state);
+            assert_eq!(engine.ledger.trial_balance(), expected.ledger.trial_balance());
+            assert_eq!(engine.violations, vec![]);
+        }
+    }
+
+    #[test]
+    fn parallel_feed_returns_csv_feed_errors() {
+        let inputs: [&[u8]; 3] = [
+            b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndeposit,1,3,abc\ndeposit,1,4,4.0",
+            b"type,client,tx,amount\ndeposit,1,1,1.0\r\ndeposit,1,2,2.0,\r\ndeposit,1,3,3.0",
+            b"type,client,tx,amount\ndeposit,1,1,\"1.0\"\ndeposit,\"1\n\",2,2.0\ndeposit,1,3,x",
+        ];
+        for input in inputs {
+            let mut expected = Engine::new(EngineConfig { audit: true, ..Default::default() });
+            let expected_error = csv_feed(input, &mut expected).unwrap_err().to_string();
+            let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+            let error = parallel_feed(input, &mut engine, 1).unwrap_err().to_string();
+            assert_eq!(error, expected_error);
+            assert_eq!(engine.clients_state, expected.clients_state);
+            assert_eq!(engine.violations, vec![]);
+        }
+    }
+
+    #[test]
+    fn output_is_ordered_filtered_and_formated_as_asked() {
+        let input = "type,client,tx,amount
+deposit,3,1,1.0
+deposit,1,2,2.0
+deposit,2,3,0.5
+withdrawal,2,4,0.5
+dispute,3,1,
+chargeback,3,1,"
+            .as_bytes();
+
+        let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+        audited_feed(input, &mut engine);
+        let write = |clients_state: Vec<(ClientId, ClientState)>, options| {
+            let mut output = Vec::new();
+            csv_writer(clients_state, &mut output, options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let first_seen: Vec<_> = engine.clients_by_first_seen().collect();
+
+        assert_eq!(
+            write(first_seen.clone(), OutputOptions::default()),
+            "client,available,held,total,locked\n1,2.0000,0.0000,2.0000,false\n2,0.0000,0.0000,0.0000,false\n3,0.0000,0.0000,0.0000,true\n"
+        );
+        assert_eq!(
+            write(first_seen.clone(), OutputOptions { order: RowOrder::AsGiven, precision: 1, ..Default::default() }),
+            "client,available,held,total,locked\n3,0.0,0.0,0.0,true\n1,2.0,0.0,2.0,false\n2,0.0,0.0,0.0,false\n"
+        );
+        assert_eq!(
+            write(first_seen.clone(), OutputOptions { filter: RowFilter::Locked, header: false, ..Default::default() }),
+            "3,0.0000,0.0000,0.0000,true\n"
+        );
+        assert_eq!(
+            write(first_seen, OutputOptions { filter: RowFilter::NonZero, header: false, ..Default::default() }),
+            "1,2.0000,0.0000,2.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn json_lines_input_is_handled_like_csv() {
+        let csv = "type,client,tx,amount,timestamp
+deposit,1,1,1.5,1646128800
+deposit,2,2,2.0,2022-03-01T10:00:00Z
+dispute,1,1,,
+withdrawal,2,3,0.5,"
+            .as_bytes();
+        let json_lines = r#"{"type":"deposit","client":1,"tx":1,"amount":1.5,"timestamp":1646128800}
+{"type":"deposit","client":2,"tx":2,"amount":2.0,"timestamp":"2022-03-01T10:00:00Z"}
+
+{"type":"dispute","client":1,"tx":1}
+{"type":"withdrawal","client":2,"tx":3,"amount":0.5,"timestamp":null}"#
+            .as_bytes();
+
+        assert_eq!(json_reader(json_lines).unwrap(), csv_reader(csv).unwrap());
+        assert!(json_reader(r#"{"type":"deposit","client":1,"tx":1,"amount":"1.0"}"#.as_bytes()).is_err());
+        assert!(json_reader(r#"{"type":"deposit","client":1,"tx":1,"amount":1.0,"timestamp":"yesterday"}"#.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn clients_are_written_as_json() {
+        let input = "type,client,tx,amount,operator\ndeposit,2,1,0.1,\ndeposit,2,2,0.2,\ndeposit,1,3,1.0,\nfreeze,1,4,,7".as_bytes();
+        let clients_state = audited_reader(input);
+
+        let mut output = Vec::new();
+        json_writer(clients_state.clone(), &mut output, OutputOptions::default()).unwrap();
+        let output: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!([
+                {"client": 1, "available": 1.0, "held": 0.0, "total": 1.0, "locked": false},
+                {"client": 2, "available": 0.3, "held": 0.0, "total": 0.3, "locked": false},
+            ])
+        );
+
+        let mut output = Vec::new();
+        let options = OutputOptions { status: true, fees: true, filter: RowFilter::NonZero, ..Default::default() };
+        json_lines_writer(clients_state, &mut output, options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"client":1,"available":1.0,"held":0.0,"total":1.0,"status":"frozen","fees":0.0}
+{"client":2,"available":0.3,"held":0.0,"total":0.3,"status":"active","fees":0.0}
+"#
+        );
+    }
+
+    /// Destination refusing every write.
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_writers_report_write_errors() {
+        let clients_state = csv_reader("type,client,tx,amount\ndeposit,1,1,1.0".as_bytes()).unwrap();
+
+        assert!(json_writer(clients_state, FailingWriter, OutputOptions::default()).is_err());
+        assert!(statements_json_writer(&[], FailingWriter).is_err());
+    }
+
+
+    #[test]
+    fn text_writers_report_write_errors() {
+        let mut engine = Engine::new(EngineConfig { audit: true, ledger: true, ..Default::default() });
+        audited_feed("type,client,tx,amount\ndeposit,1,1,1.0".as_bytes(), &mut engine);
+
+        assert!(ledger_writer(&engine.ledger.entries, "USD", FailingWriter).is_err());
+        assert!(beancount_writer(&engine.ledger.entries, "USD", FailingWriter).is_err());
+        assert!(statements_csv_writer(&[], FailingWriter).is_err());
+        assert!(trial_balance_writer(&engine.ledger.trial_balance(), FailingWriter).is_err());
+        assert!(transactions_writer([], None, FailingWriter).is_err());
+    }
+}
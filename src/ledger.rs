@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::engine::{ClientId, Timestamp, Transaction, TransactionId};
+
+/// Maximum difference tolerated between debits and credits, relative to their size, amounts being floats.
+const TOLERANCE: f64 = 1e-9;
+
+/// Balance of a client, each one being a separate account of the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClientBalance {
+    Available,
+    Held,
+    /// Deposited funds not cleared yet
+    Pending,
+}
+
+/// Account of the general ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Account {
+    /// Cash held by the bank, moved by deposits, withdrawals, refunds and payouts
+    BankCash,
+    /// Funds charged back to the payers' banks, credited by chargebacks and debited by their reversals
+    Chargebacks,
+    /// Fees collected from clients
+    FeeIncome,
+    /// Interest paid to clients
+    InterestExpense,
+    /// Overdraft interest charged to clients
+    InterestIncome,
+    /// Funds owed to a client
+    Client(ClientId, ClientBalance),
+}
+
+/// Names accounts the way plain-text accounting tools expect them, e.g. `Liabilities:Clients:1:Available`.
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::BankCash => write!(f, "Assets:Cash"),
+            Account::Chargebacks => write!(f, "Liabilities:Chargebacks"),
+            Account::FeeIncome => write!(f, "Income:Fees"),
+            Account::InterestExpense => write!(f, "Expenses:Interest"),
+            Account::InterestIncome => write!(f, "Income:Interest"),
+            Account::Client(client, balance) => write!(f, "Liabilities:Clients:{client}:{balance:?}"),
+        }
+    }
+}
+
+/// Represents a balanced journal entry moving `amount` from the credited account to the debited account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub client: ClientId,
+    pub tx: TransactionId,
+    /// Type of the transaction, or "fee" for fees charged on it
+    pub kind: String,
+    pub timestamp: Option<Timestamp>,
+    pub debit: Account,
+    pub credit: Account,
+    pub amount: f64,
+}
+
+impl LedgerEntry {
+    pub fn new(transaction: &Transaction, debit: Account, credit: Account, amount: f64) -> Self {
+        LedgerEntry {
+            client: transaction.client,
+            tx: transaction.tx,
+            kind: transaction.tx_type.as_str().to_string(),
+            timestamp: transaction.timestamp,
+            debit,
+            credit,
+            amount,
+        }
+    }
+}
+
+/// Debits and credits of every account, along with the entries posted if they are kept.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    /// Entries posted so far, in order, only kept if `keep_entries` is set
+    pub entries: Vec<LedgerEntry>,
+    pub keep_entries: bool,
+    debits: BTreeMap<Account, f64>,
+    credits: BTreeMap<Account, f64>,
+}
+
+impl Ledger {
+    pub fn new(keep_entries: bool) -> Self {
+        Ledger {
+            keep_entries,
+            ..Default::default()
+        }
+    }
+
+    pub fn post(&mut self, entry: LedgerEntry) {
+        *self.debits.entry(entry.debit).or_default() += entry.amount;
+        *self.credits.entry(entry.credit).or_default() += entry.amount;
+        if self.keep_entries {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Debits minus credits of the account, client accounts having a credit balance.
+    pub fn balance(&self, account: Account) -> f64 {
+        self.debits.get(&account).copied().unwrap_or_default() - self.credits.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Debits and credits per account, in account order.
+    pub fn trial_balance(&self) -> TrialBalance {
+        let mut accounts: Vec<Account> = self.debits.keys().chain(self.credits.keys()).copied().collect();
+        accounts.sort_unstable();
+        accounts.dedup();
+        let lines: Vec<TrialBalanceLine> = accounts
+            .into_iter()
+            .map(|account| TrialBalanceLine {
+                account,
+                debit: self.debits.get(&account).copied().unwrap_or_default(),
+                credit: self.credits.get(&account).copied().unwrap_or_default(),
+            })
+            .collect();
+        TrialBalance {
+            debits: lines.iter().map(|line| line.debit).sum(),
+            credits: lines.iter().map(|line| line.credit).sum(),
+            lines,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrialBalanceLine {
+    pub account: Account,
+    pub debit: f64,
+    pub credit: f64,
+}
+
+/// Report of the debits and credits of every account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrialBalance {
+    pub lines: Vec<TrialBalanceLine>,
+    pub debits: f64,
+    pub credits: f64,
+}
+
+impl TrialBalance {
+    /// Checks that debits equal credits.
+    pub fn is_balanced(&self) -> bool {
+        (self.debits - self.credits).abs() <= TOLERANCE * self.debits.abs().max(self.credits.abs()).max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TransactionType;
+
+    #[test]
+    fn trial_balance_sums_debits_and_credits_per_account() {
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(2.0),
+            operator: None,
+            timestamp: None,
+            seq: None,
+            currency: None,
+        };
+        let available = Account::Client(1, ClientBalance::Available);
+        let mut ledger = Ledger::new(true);
+        ledger.post(LedgerEntry::new(&deposit, Account::BankCash, available, 2.0));
+        ledger.post(LedgerEntry::new(&deposit, available, Account::FeeIncome, 0.5));
+
+        let trial_balance = ledger.trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.debits, 2.5);
+        assert_eq!(trial_balance.lines[0], TrialBalanceLine { account: Account::BankCash, debit: 2.0, credit: 0.0 });
+        assert_eq!(ledger.balance(available), -1.5);
+        assert_eq!(ledger.entries.len(), 2);
+        assert_eq!(available.to_string(), "Liabilities:Clients:1:Available");
+    }
+}
@@ -1,2 +1,19 @@
+pub mod admin;
+pub mod audit;
 pub mod engine;
+pub mod export;
+pub mod fees;
+pub mod generate;
+pub mod history;
+pub mod interest;
 pub mod io;
+pub mod ledger;
+pub mod limits;
+pub mod parallel;
+pub mod parse;
+pub mod reorder;
+pub mod statement;
+pub mod status;
+
+#[cfg(test)]
+mod properties;
@@ -0,0 +1,284 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::engine::{ClientId, Timestamp, TransactionId};
+use crate::status::Operation;
+
+pub const SECONDS_PER_DAY: Timestamp = 86_400;
+
+/// Tier used by clients that have no tier assigned.
+pub const DEFAULT_TIER: &str = "default";
+
+/// Period over which totals and counts are accumulated.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Window {
+    /// Calendar day (UTC) of the transaction
+    Daily,
+    /// Last given number of seconds, up to and including the transaction
+    Rolling(Timestamp),
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "daily" => Ok(Window::Daily),
+            seconds => seconds
+                .parse()
+                .map(Window::Rolling)
+                .map_err(|_| format!("invalid window {seconds}, expected daily or a number of seconds")),
+        }
+    }
+}
+
+impl TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Window {
+    fn contains(&self, timestamp: Timestamp, now: Timestamp) -> bool {
+        match self {
+            Window::Daily => timestamp / SECONDS_PER_DAY == now / SECONDS_PER_DAY,
+            Window::Rolling(seconds) => timestamp.saturating_add(*seconds) > now,
+        }
+    }
+
+    fn length(&self) -> Timestamp {
+        match self {
+            Window::Daily => SECONDS_PER_DAY,
+            Window::Rolling(seconds) => *seconds,
+        }
+    }
+}
+
+/// What happens to a transaction breaching a limit.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    /// The transaction is not applied
+    #[default]
+    Reject,
+    /// The transaction is applied but the breach is recorded
+    Flag,
+}
+
+/// Represents a line of the limits configuration.
+///
+/// A rule applies either to a single client or to every client of a tier.
+/// Client rules for an operation replace the tier rules for that operation.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LimitRule {
+    pub client: Option<ClientId>,
+    pub tier: Option<String>,
+    /// One of deposit, withdrawal, other operations being rejected
+    #[serde(deserialize_with = "deserialize_operation")]
+    pub operation: Operation,
+    /// Maximum amount of a single transaction
+    pub max_amount: Option<f64>,
+    /// Window over which `max_total` and `max_count` are enforced
+    pub window: Option<Window>,
+    pub max_total: Option<f64>,
+    pub max_count: Option<usize>,
+    #[serde(default)]
+    pub action: LimitAction,
+}
+
+fn deserialize_operation<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Operation, D::Error> {
+    match Operation::deserialize(deserializer)? {
+        operation @ (Operation::Deposit | Operation::Withdrawal) => Ok(operation),
+        operation => Err(serde::de::Error::custom(format!(
+            "limits only apply to deposit and withdrawal, not {}",
+            format!("{operation:?}").to_lowercase()
+        ))),
+    }
+}
+
+/// Represents a line of the tiers configuration, assigning a client to a tier.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientTier {
+    pub client: ClientId,
+    pub tier: String,
+}
+
+/// Reason why a transaction breached a limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreachReason {
+    MaxAmount { limit: f64, amount: f64 },
+    MaxTotal { limit: f64, total: f64 },
+    MaxCount { limit: usize },
+}
+
+impl fmt::Display for BreachReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreachReason::MaxAmount { limit, amount } => {
+                write!(f, "amount {amount:.4} exceeds the single transaction limit of {limit:.4}")
+            }
+            BreachReason::MaxTotal { limit, total } => {
+                write!(f, "window total {total:.4} would exceed the limit of {limit:.4}")
+            }
+            BreachReason::MaxCount { limit } => {
+                write!(f, "window count would exceed the limit of {limit} transactions")
+            }
+        }
+    }
+}
+
+/// Represents a transaction that breached a limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitBreach {
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub operation: Operation,
+    pub reason: BreachReason,
+    pub action: LimitAction,
+}
+
+impl fmt::Display for LimitBreach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            LimitAction::Reject => "rejected",
+            LimitAction::Flag => "flagged",
+        };
+        write!(f, "transaction {} of client {} {}: {}", self.tx, self.client, action, self.reason)
+    }
+}
+
+/// Limit rules per client and per tier.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Limits {
+    by_client: HashMap<(ClientId, Operation), Vec<LimitRule>>,
+    /// Keyed by tier first, so that a tier can be looked up without allocating
+    by_tier: HashMap<String, HashMap<Operation, Vec<LimitRule>>>,
+    client_tiers: HashMap<ClientId, String>,
+}
+
+impl Limits {
+    pub fn new(rules: impl IntoIterator<Item = LimitRule>, client_tiers: impl IntoIterator<Item = ClientTier>) -> Self {
+        let mut limits = Limits {
+            client_tiers: client_tiers.into_iter().map(|assignment| (assignment.client, assignment.tier)).collect(),
+            ..Default::default()
+        };
+        for rule in rules {
+            match (rule.client, &rule.tier) {
+                (Some(client), _) => limits.by_client.entry((client, rule.operation)).or_default().push(rule),
+                (None, tier) => {
+                    let tier = tier.clone().unwrap_or_else(|| DEFAULT_TIER.to_string());
+                    limits.by_tier.entry(tier).or_default().entry(rule.operation).or_default().push(rule)
+                }
+            }
+        }
+        limits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_client.is_empty() && self.by_tier.is_empty()
+    }
+
+    fn rules_for(&self, client: ClientId, operation: Operation) -> &[LimitRule] {
+        if let Some(rules) = self.by_client.get(&(client, operation)) {
+            return rules;
+        }
+        let tier = self.client_tiers.get(&client).map(String::as_str).unwrap_or(DEFAULT_TIER);
+        self.by_tier
+            .get(tier)
+            .and_then(|rules| rules.get(&operation))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Checks a transaction against the rules of the client and returns every breached limit.
+    ///
+    /// Window limits are only checked when the transaction has a timestamp.
+    pub fn check(
+        &self,
+        client: ClientId,
+        operation: Operation,
+        amount: f64,
+        timestamp: Option<Timestamp>,
+        window: &VelocityWindow,
+    ) -> Vec<(BreachReason, LimitAction)> {
+        let mut breaches = Vec::new();
+        for rule in self.rules_for(client, operation) {
+            if let Some(limit) = rule.max_amount {
+                if amount > limit {
+                    breaches.push((BreachReason::MaxAmount { limit, amount }, rule.action));
+                }
+            }
+            let (Some(period), Some(now)) = (rule.window, timestamp) else {
+                continue;
+            };
+            let (count, total) = window.summarize(period, now);
+            if let Some(limit) = rule.max_total {
+                if total + amount > limit {
+                    breaches.push((BreachReason::MaxTotal { limit, total: total + amount }, rule.action));
+                }
+            }
+            if let Some(limit) = rule.max_count {
+                if count + 1 > limit {
+                    breaches.push((BreachReason::MaxCount { limit }, rule.action));
+                }
+            }
+        }
+        breaches
+    }
+
+    /// Longest window configured, entries older than that can be forgotten.
+    pub fn longest_window(&self) -> Timestamp {
+        self.by_client
+            .values()
+            .chain(self.by_tier.values().flat_map(HashMap::values))
+            .flatten()
+            .filter_map(|rule| rule.window.map(|window| window.length()))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Applied transactions of a client for an operation, kept as long as a window may need them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VelocityWindow {
+    entries: VecDeque<(Timestamp, f64)>,
+}
+
+impl VelocityWindow {
+    /// Records an applied transaction and forgets the ones older than `retention` seconds.
+    pub fn record(&mut self, timestamp: Timestamp, amount: f64, retention: Timestamp) {
+        self.entries.push_back((timestamp, amount));
+        while let Some((oldest, _)) = self.entries.front() {
+            if oldest.saturating_add(retention) > timestamp {
+                break;
+            }
+            self.entries.pop_front();
+        }
+    }
+
+    /// Count and total of the recorded transactions falling in the window.
+    fn summarize(&self, window: Window, now: Timestamp) -> (usize, f64) {
+        self.entries
+            .iter()
+            .filter(|(timestamp, _)| window.contains(*timestamp, now))
+            .fold((0, 0.0), |(count, total), (_, amount)| (count + 1, total + amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_is_parsed_from_daily_or_seconds() {
+        assert_eq!("daily".parse::<Window>(), Ok(Window::Daily));
+        assert_eq!("3600".parse::<Window>(), Ok(Window::Rolling(3600)));
+        assert!("weekly".parse::<Window>().is_err());
+    }
+}
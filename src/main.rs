@@ -1,22 +1,469 @@
 use std::fs::File;
 
-use payment_engine::io::{csv_reader, csv_writer};
+use payment_engine::engine::{DeadlinePolicy, Engine, EngineConfig};
+use payment_engine::export::{beancount_writer, ledger_writer};
+use payment_engine::generate::{Generator, GeneratorConfig};
+use payment_engine::interest::InterestPolicy;
+use payment_engine::io::{
+    credit_limits_reader, csv_feed, csv_feed_daily, csv_feed_reordered, csv_writer, fee_schedule_reader, json_feed,
+    json_lines_writer, json_writer, limits_reader, permissions_reader, statements_csv_writer, statements_json_writer,
+    trial_balance_writer, transactions_writer, OutputOptions, RowFilter, RowOrder,
+};
+use payment_engine::parallel::mmap_feed;
+use payment_engine::reorder::{ReorderBuffer, DEFAULT_CAPACITY};
+use payment_engine::statement::DailyClose;
 
-/// Entrypoint of the application, filepath expected
+/// Format of the transactions read or of the clients written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    /// A JSON array, only written
+    Json,
+    JsonLines,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "jsonl" => Some(Format::JsonLines),
+            _ => None,
+        }
+    }
+
+    /// Format of the file, CSV unless its extension is `.json`, `.jsonl` or `.ndjson`.
+    fn from_path(path: &str) -> Self {
+        match path.rsplit_once('.').map(|(_, extension)| extension) {
+            Some("json") => Format::Json,
+            Some("jsonl" | "ndjson") => Format::JsonLines,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// Command line arguments:
+/// `<transactions.csv|transactions.jsonl> [--input-format csv|jsonl] [--output-format csv|json|jsonl] [--fees <fee_schedule.csv>] [--permissions <permissions.csv>] [--credit-limits <credit_limits.csv>]
+/// [--limits <limits.csv> [--tiers <tiers.csv>]] [--reorder-window <n> [--reorder-capacity <n>]]
+/// [--pending-deposits [--clearing-delay <seconds>]] [--dispute-window <seconds>] [--dispute-deadline <seconds> [--deadline-policy resolve|chargeback]]
+/// [--interest-rate <annual %>] [--overdraft-rate <annual %>] [--eod-cutoff <seconds>] [--statements <statements.csv|statements.json>]
+/// [--trial-balance <trial_balance.csv>] [--export <journal.ledger|journal.beancount>] [--commodity <code>]
+/// [--audit] [--parallel] [--unlock-on-reversal] [--status] [--order client|first-seen] [--only locked|non-zero]
+/// [--precision <decimals>] [--no-header]`
+#[derive(Debug)]
+struct Args {
+    path: String,
+    fees_path: Option<String>,
+    permissions_path: Option<String>,
+    credit_limits_path: Option<String>,
+    limits_path: Option<String>,
+    tiers_path: Option<String>,
+    reorder_window: Option<u64>,
+    reorder_capacity: usize,
+    pending_deposits: bool,
+    clearing_delay: Option<u64>,
+    dispute_window: Option<u64>,
+    dispute_deadline: Option<u64>,
+    deadline_policy: DeadlinePolicy,
+    interest_rate: Option<f64>,
+    overdraft_rate: Option<f64>,
+    eod_cutoff: u64,
+    statements_path: Option<String>,
+    trial_balance_path: Option<String>,
+    export_path: Option<String>,
+    commodity: String,
+    audit: bool,
+    parallel: bool,
+    unlock_on_reversal: bool,
+    status: bool,
+    order: RowOrder,
+    filter: RowFilter,
+    precision: usize,
+    header: bool,
+    input_format: Format,
+    output_format: Format,
+}
+
+fn parse_args() -> Args {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut fees_path = None;
+    let mut permissions_path = None;
+    let mut credit_limits_path = None;
+    let mut limits_path = None;
+    let mut tiers_path = None;
+    let mut reorder_window = None;
+    let mut reorder_capacity = DEFAULT_CAPACITY;
+    let mut pending_deposits = false;
+    let mut clearing_delay = None;
+    let mut dispute_window = None;
+    let mut dispute_deadline = None;
+    let mut deadline_policy = DeadlinePolicy::default();
+    let mut interest_rate = None;
+    let mut overdraft_rate = None;
+    let mut eod_cutoff = 0;
+    let mut statements_path = None;
+    let mut trial_balance_path = None;
+    let mut export_path = None;
+    let mut commodity = "USD".to_string();
+    let mut audit = false;
+    let mut parallel = false;
+    let mut unlock_on_reversal = false;
+    let mut status = false;
+    let mut order = RowOrder::default();
+    let mut filter = RowFilter::default();
+    let mut precision = OutputOptions::default().precision;
+    let mut header = true;
+    let mut input_format = None;
+    let mut output_format = Format::Csv;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fees" => fees_path = Some(args.next().expect("Error: missing value for --fees")),
+            "--permissions" => permissions_path = Some(args.next().expect("Error: missing value for --permissions")),
+            "--credit-limits" => credit_limits_path = Some(args.next().expect("Error: missing value for --credit-limits")),
+            "--limits" => limits_path = Some(args.next().expect("Error: missing value for --limits")),
+            "--tiers" => tiers_path = Some(args.next().expect("Error: missing value for --tiers")),
+            "--reorder-window" => {
+                let value = args.next().expect("Error: missing value for --reorder-window");
+                reorder_window = Some(value.parse().expect("Error: --reorder-window expects a number"));
+            }
+            "--reorder-capacity" => {
+                let value = args.next().expect("Error: missing value for --reorder-capacity");
+                reorder_capacity = value.parse().expect("Error: --reorder-capacity expects a number");
+            }
+            "--pending-deposits" => pending_deposits = true,
+            "--clearing-delay" => {
+                let value = args.next().expect("Error: missing value for --clearing-delay");
+                clearing_delay = Some(value.parse().expect("Error: --clearing-delay expects a number of seconds"));
+            }
+            "--dispute-window" => {
+                let value = args.next().expect("Error: missing value for --dispute-window");
+                dispute_window = Some(value.parse().expect("Error: --dispute-window expects a number of seconds"));
+            }
+            "--dispute-deadline" => {
+                let value = args.next().expect("Error: missing value for --dispute-deadline");
+                dispute_deadline = Some(value.parse().expect("Error: --dispute-deadline expects a number of seconds"));
+            }
+            "--deadline-policy" => {
+                deadline_policy = match args.next().as_deref() {
+                    Some("resolve") => DeadlinePolicy::Resolve,
+                    Some("chargeback") => DeadlinePolicy::Chargeback,
+                    _ => panic!("Error: --deadline-policy expects resolve or chargeback"),
+                }
+            }
+            "--interest-rate" => {
+                let value = args.next().expect("Error: missing value for --interest-rate");
+                interest_rate = Some(value.parse().expect("Error: --interest-rate expects a percentage"));
+            }
+            "--overdraft-rate" => {
+                let value = args.next().expect("Error: missing value for --overdraft-rate");
+                overdraft_rate = Some(value.parse().expect("Error: --overdraft-rate expects a percentage"));
+            }
+            "--eod-cutoff" => {
+                let value = args.next().expect("Error: missing value for --eod-cutoff");
+                eod_cutoff = value.parse().expect("Error: --eod-cutoff expects a number of seconds");
+            }
+            "--statements" => statements_path = Some(args.next().expect("Error: missing value for --statements")),
+            "--trial-balance" => trial_balance_path = Some(args.next().expect("Error: missing value for --trial-balance")),
+            "--export" => export_path = Some(args.next().expect("Error: missing value for --export")),
+            "--commodity" => commodity = args.next().expect("Error: missing value for --commodity"),
+            "--audit" => audit = true,
+            "--parallel" => parallel = true,
+            "--unlock-on-reversal" => unlock_on_reversal = true,
+            "--status" => status = true,
+            "--order" => {
+                order = match args.next().as_deref() {
+                    Some("client") => RowOrder::ClientId,
+                    Some("first-seen") => RowOrder::AsGiven,
+                    _ => panic!("Error: --order expects client or first-seen"),
+                }
+            }
+            "--only" => {
+                filter = match args.next().as_deref() {
+                    Some("locked") => RowFilter::Locked,
+                    Some("non-zero") => RowFilter::NonZero,
+                    _ => panic!("Error: --only expects locked or non-zero"),
+                }
+            }
+            "--precision" => {
+                let value = args.next().expect("Error: missing value for --precision");
+                precision = value.parse().expect("Error: --precision expects a number of decimals");
+            }
+            "--no-header" => header = false,
+            "--input-format" => {
+                input_format = match args.next().as_deref().and_then(Format::from_name) {
+                    Some(Format::Json) | None => panic!("Error: --input-format expects csv or jsonl"),
+                    format => format,
+                }
+            }
+            "--output-format" => {
+                let value = args.next();
+                output_format = value.as_deref().and_then(Format::from_name).expect("Error: --output-format expects csv, json or jsonl");
+            }
+            _ if arg.starts_with("--") => panic!("Error: unknown argument {arg}"),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.expect("Error: missing filepath parameter");
+    let input_format = input_format.unwrap_or_else(|| Format::from_path(&path));
+    assert!(input_format != Format::Json, "Error: JSON transactions are read as JSON Lines, one object per line (.jsonl or .ndjson)");
+    Args {
+        path,
+        fees_path,
+        permissions_path,
+        credit_limits_path,
+        limits_path,
+        tiers_path,
+        reorder_window,
+        reorder_capacity,
+        pending_deposits,
+        clearing_delay,
+        dispute_window,
+        dispute_deadline,
+        deadline_policy,
+        interest_rate,
+        overdraft_rate,
+        eod_cutoff,
+        statements_path,
+        trial_balance_path,
+        export_path,
+        commodity,
+        audit,
+        parallel,
+        unlock_on_reversal,
+        status,
+        order,
+        filter,
+        precision,
+        header,
+        input_format,
+        output_format,
+    }
+}
+
+/// Rows generated when neither `--rows` nor `--size` is given.
+const DEFAULT_ROWS: u64 = 2_000;
+
+/// Arguments of the `generate` subcommand:
+/// `generate [--seed <n>] [--clients <n>] [--rows <n>] [--size <bytes, e.g. 500M or 2G>]
+/// [--mix deposit=<weight>,withdrawal=<weight>] [--dispute-ratio <ratio>] [--chargeback-ratio <ratio>]
+/// [--edge-cases <ratio>] [--unlock-ratio <ratio>] [--output <transactions.csv>]`
+#[derive(Debug)]
+struct GenerateArgs {
+    config: GeneratorConfig,
+    rows: Option<u64>,
+    size: Option<u64>,
+    output_path: Option<String>,
+}
+
+/// Parses a size in bytes, optionally followed by K, M or G.
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, unit) = match value.char_indices().last()? {
+        (index, 'K' | 'k') => (&value[..index], 1 << 10),
+        (index, 'M' | 'm') => (&value[..index], 1 << 20),
+        (index, 'G' | 'g') => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+fn parse_generate_args() -> GenerateArgs {
+    let mut args = std::env::args().skip(2);
+    let mut config = GeneratorConfig::default();
+    let mut rows = None;
+    let mut size = None;
+    let mut output_path = None;
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| panic!("Error: missing value for {arg}"));
+        match arg.as_str() {
+            "--seed" => config.seed = value.parse().expect("Error: --seed expects a number"),
+            "--clients" => {
+                config.clients = value.parse().expect("Error: --clients expects a number");
+                assert!(config.clients > 0, "Error: --clients expects at least 1 client");
+            }
+            "--rows" => rows = Some(value.parse().expect("Error: --rows expects a number")),
+            "--size" => size = Some(parse_size(&value).expect("Error: --size expects a number of bytes, optionally followed by K, M or G")),
+            "--mix" => {
+                for weight in value.split(',') {
+                    match weight.split_once('=') {
+                        Some(("deposit", weight)) => config.deposit_weight = weight.parse().expect("Error: --mix expects whole weights"),
+                        Some(("withdrawal", weight)) => config.withdrawal_weight = weight.parse().expect("Error: --mix expects whole weights"),
+                        _ => panic!("Error: --mix expects deposit=<weight>,withdrawal=<weight>"),
+                    }
+                }
+            }
+            "--dispute-ratio" => config.dispute_ratio = value.parse().expect("Error: --dispute-ratio expects a ratio"),
+            "--chargeback-ratio" => config.chargeback_ratio = value.parse().expect("Error: --chargeback-ratio expects a ratio"),
+            "--edge-cases" => config.edge_case_ratio = value.parse().expect("Error: --edge-cases expects a ratio"),
+            "--unlock-ratio" => config.unlock_ratio = value.parse().expect("Error: --unlock-ratio expects a ratio"),
+            "--output" => output_path = Some(value),
+            _ => panic!("Error: unknown argument {arg} for generate"),
+        }
+    }
+    GenerateArgs {
+        config,
+        rows,
+        size,
+        output_path,
+    }
+}
+
+/// Writes a generated workload to the output file, or to stdout.
+fn generate(args: GenerateArgs) -> Result<(), std::io::Error> {
+    let rows = match (args.rows, args.size) {
+        (None, None) => DEFAULT_ROWS,
+        (rows, _) => rows.unwrap_or(u64::MAX),
+    };
+    let transactions = Generator::new(args.config).take(usize::try_from(rows).unwrap_or(usize::MAX));
+    match args.output_path {
+        Some(output_path) => transactions_writer(transactions, args.size, File::create(output_path)?),
+        None => transactions_writer(transactions, args.size, std::io::stdout().lock()),
+    }
+}
+
+/// Entrypoint of the application, filepath expected, or `generate` followed by its arguments
 fn main() -> Result<(), std::io::Error> {
-    let path = std::env::args()
-        .nth(1)
-        .expect("Error: missing filepath parameter");
-    let csv_file = File::open(path)?;
+    if std::env::args().nth(1).as_deref() == Some("generate") {
+        return generate(parse_generate_args());
+    }
+    let args = parse_args();
+    let input_file = File::open(&args.path)?;
 
-    match csv_reader(&csv_file) {
+    let mut config = EngineConfig {
+        currency: Some(args.commodity.clone()),
+        unlock_on_reversal: args.unlock_on_reversal,
+        pending_deposits: args.pending_deposits,
+        clearing_delay: args.clearing_delay,
+        dispute_window: args.dispute_window,
+        dispute_deadline: args.dispute_deadline,
+        deadline_policy: args.deadline_policy,
+        interest: (args.interest_rate.is_some() || args.overdraft_rate.is_some()).then(|| InterestPolicy {
+            rate: args.interest_rate.unwrap_or_default(),
+            overdraft_rate: args.overdraft_rate.unwrap_or_default(),
+            cutoff: args.eod_cutoff,
+        }),
+        journal: args.statements_path.is_some(),
+        ledger: args.export_path.is_some(),
+        audit: args.audit,
+        ..Default::default()
+    };
+    let mut options = OutputOptions {
+        status: args.status,
+        pending: args.pending_deposits,
+        order: args.order,
+        filter: args.filter,
+        precision: args.precision,
+        header: args.header,
+        ..Default::default()
+    };
+    if let Some(permissions_path) = args.permissions_path {
+        match permissions_reader(File::open(permissions_path)?) {
+            Err(err) => panic!("{err}"),
+            Ok(permissions) => config.permissions = permissions,
+        }
+    }
+    if let Some(credit_limits_path) = args.credit_limits_path {
+        match credit_limits_reader(File::open(credit_limits_path)?) {
+            Err(err) => panic!("{err}"),
+            Ok(credit_limits) => config.credit_limits = credit_limits,
+        }
+        options.overdrawn = true;
+    }
+    if let Some(limits_path) = args.limits_path {
+        let limits = match args.tiers_path {
+            Some(tiers_path) => limits_reader(File::open(limits_path)?, File::open(tiers_path)?),
+            None => limits_reader(File::open(limits_path)?, "client,tier".as_bytes()),
+        };
+        match limits {
+            Err(err) => panic!("{err}"),
+            Ok(limits) => config.limits = limits,
+        }
+    }
+    if let Some(fees_path) = args.fees_path {
+        match fee_schedule_reader(File::open(fees_path)?) {
+            Err(err) => panic!("{err}"),
+            Ok(fee_schedule) => config.fee_schedule = fee_schedule,
+        }
+        options.fees = true;
+    }
+    let mut engine = Engine::new(config);
+
+    let mut close = DailyClose::new(args.eod_cutoff);
+    let fed = match (args.reorder_window, &args.statements_path) {
+        (Some(_), Some(_)) => panic!("Error: --statements can't be combined with --reorder-window"),
+        (Some(_), None) | (None, Some(_)) if args.parallel => {
+            panic!("Error: --parallel can't be combined with --reorder-window or --statements")
+        }
+        (Some(_), None) | (None, Some(_)) if args.input_format == Format::JsonLines => {
+            panic!("Error: JSON Lines input can't be combined with --reorder-window or --statements")
+        }
+        (Some(window), None) => csv_feed_reordered(&input_file, &mut engine, &mut ReorderBuffer::new(window, args.reorder_capacity)),
+        (None, Some(_)) => csv_feed_daily(&input_file, &mut engine, &mut close),
+        (None, None) if args.input_format == Format::JsonLines => {
+            assert!(!args.parallel, "Error: --parallel can't be combined with JSON Lines input");
+            json_feed(&input_file, &mut engine)
+        }
+        (None, None) if args.parallel => mmap_feed(&args.path, &mut engine),
+        (None, None) => csv_feed(&input_file, &mut engine),
+    };
+    match fed {
         Err(err) => panic!("{err}"),
-        Ok(clients_state) => {
+        Ok(()) => {
+            for breach in &engine.limit_breaches {
+                eprintln!("Warning: {breach}");
+            }
+            let trial_balance = engine.ledger.trial_balance();
+            if !trial_balance.is_balanced() {
+                eprintln!(
+                    "Error: ledger is unbalanced (debits {:.4}, credits {:.4})",
+                    trial_balance.debits, trial_balance.credits
+                );
+            }
+            if let Some(trial_balance_path) = args.trial_balance_path {
+                trial_balance_writer(&trial_balance, File::create(trial_balance_path)?)?;
+            }
+            if let Some(export_path) = args.export_path {
+                let export_file = File::create(&export_path)?;
+                if export_path.ends_with(".beancount") {
+                    beancount_writer(&engine.ledger.entries, &args.commodity, export_file)?;
+                } else {
+                    ledger_writer(&engine.ledger.entries, &args.commodity, export_file)?;
+                }
+            }
+            if let Some(statements_path) = args.statements_path {
+                for statement in close.statements.iter().filter(|statement| !statement.reconciles()) {
+                    eprintln!(
+                        "Error: statement of client {} for day {} doesn't reconcile (net movement {:.4})",
+                        statement.client,
+                        statement.day.map(|day| day.to_string()).unwrap_or_default(),
+                        statement.net_movement()
+                    );
+                }
+                let statements_file = File::create(&statements_path)?;
+                if statements_path.ends_with(".json") {
+                    if let Err(err) = statements_json_writer(&close.statements, statements_file) {
+                        panic!("{err}");
+                    }
+                } else {
+                    statements_csv_writer(&close.statements, statements_file)?;
+                }
+            }
             let stdout = std::io::stdout();
             let handle = stdout.lock(); // better performance on single threaded program
-            csv_writer(clients_state, handle)?
+            let clients_state: Vec<_> = match options.order {
+                RowOrder::ClientId => engine.clients_state.into_iter().collect(),
+                RowOrder::AsGiven => engine.clients_by_first_seen().collect(),
+            };
+            let written = match args.output_format {
+                Format::Csv => csv_writer(clients_state, handle, options).map_err(Into::into),
+                Format::Json => json_writer(clients_state, handle, options),
+                Format::JsonLines => json_lines_writer(clients_state, handle, options),
+            };
+            if let Err(err) = written {
+                panic!("{err}");
+            }
         }
-    }  
+    }
 
     Ok(())
 }
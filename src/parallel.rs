@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::engine::{Engine, Transaction};
+use crate::parse::TransactionReader;
+
+/// Size of the blocks parsed in parallel, a block ending at the first newline past this size.
+pub const DEFAULT_BLOCK_SIZE: usize = 8 << 20;
+
+/// Rows parsed from a block, up to the first error if any.
+struct ParsedBlock {
+    transactions: Vec<Transaction>,
+    failed: bool,
+}
+
+/// Splits `bytes` into blocks of at least `block_size` bytes ending right after a newline, the last one excepted.
+fn blocks(bytes: &[u8], block_size: usize) -> Vec<&[u8]> {
+    let mut blocks = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let end = rest
+            .get(block_size.max(1)..)
+            .and_then(|tail| tail.iter().position(|byte| *byte == b'\n'))
+            .map_or(rest.len(), |newline| block_size.max(1) + newline + 1);
+        let (block, tail) = rest.split_at(end);
+        blocks.push(block);
+        rest = tail;
+    }
+    blocks
+}
+
+fn parse_block(header: &[u8], block: &[u8]) -> ParsedBlock {
+    let mut parsed = ParsedBlock { transactions: Vec::new(), failed: false };
+    let Ok(mut reader) = TransactionReader::new(std::io::Read::chain(header, block)) else {
+        parsed.failed = true;
+        return parsed;
+    };
+    let mut transaction = Transaction::default();
+    loop {
+        match reader.read(&mut transaction) {
+            Ok(true) => parsed.transactions.push(transaction.clone()),
+            Ok(false) => return parsed,
+            Err(_) => {
+                parsed.failed = true;
+                return parsed;
+            }
+        }
+    }
+}
+
+/// Reads the rows sequentially, skipping the first `skip` rows, and feeds the others to the engine.
+fn sequential_feed(bytes: &[u8], skip: usize, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+    let mut reader = TransactionReader::new(bytes)?;
+    let mut transaction = Transaction::default();
+    let mut row = 0;
+    while reader.read(&mut transaction)? {
+        if row >= skip {
+            engine.handle_transaction(&transaction);
+        }
+        row += 1;
+    }
+    Ok(())
+}
+
+/// Parses CSV bytes in parallel and feeds each transaction to the engine in file order.
+///
+/// The rows are split into blocks at newline boundaries, batches of blocks are parsed on the rayon thread pool,
+/// then handled in order, so that the engine sees exactly what `csv_feed` would give it.
+/// Memory is bounded by the batch: one block per thread.
+/// Input with quotes, whose fields may span lines, is read sequentially.
+/// When a block fails to parse, the rows are read sequentially from the start of that block,
+/// returning the error `csv_feed` would return.
+///
+/// # Arguments
+///
+/// `bytes` - CSV source, e.g. a memory-mapped file
+/// `engine` - engine handling the transactions
+/// `block_size` - minimum size of the blocks parsed in parallel
+pub fn parallel_feed(bytes: &[u8], engine: &mut Engine, block_size: usize) -> Result<(), Box<dyn Error>> {
+    let header_end = bytes.iter().position(|byte| *byte == b'\n').map(|newline| newline + 1);
+    let Some(header_end) = header_end.filter(|_| !bytes.contains(&b'"')) else {
+        return sequential_feed(bytes, 0, engine);
+    };
+    let (header, rows) = bytes.split_at(header_end);
+    let blocks = blocks(rows, block_size);
+    let mut handled = 0;
+    for batch in blocks.chunks(rayon::current_num_threads()) {
+        let parsed: Vec<ParsedBlock> = batch.par_iter().map(|block| parse_block(header, block)).collect();
+        for block in parsed {
+            if block.failed {
+                return sequential_feed(bytes, handled, engine);
+            }
+            for transaction in &block.transactions {
+                engine.handle_transaction(transaction);
+            }
+            handled += block.transactions.len();
+        }
+    }
+    Ok(())
+}
+
+/// Memory-maps a CSV file and feeds each transaction to the engine, parsing in parallel.
+///
+/// # Arguments
+///
+/// `path` - file on local disk, which must not be modified while it is read
+/// `engine` - engine handling the transactions
+pub fn mmap_feed(path: impl AsRef<Path>, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+    let file = File::open(path)?;
+    // Safety: the file is only read, and must not be truncated or modified by another process meanwhile
+    let mmap = unsafe { Mmap::map(&file)? };
+    parallel_feed(&mmap, engine, DEFAULT_BLOCK_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_end_at_newlines() {
+        let rows = b"deposit,1,1,1.0\ndeposit,1,2,1.0\ndeposit,1,3,1.0";
+
+        assert_eq!(blocks(rows, 20), vec![&rows[..32], &rows[32..]]);
+        assert_eq!(blocks(rows, 1), vec![&rows[..16], &rows[16..32], &rows[32..]]);
+        assert_eq!(blocks(rows, 100), vec![&rows[..]]);
+        assert!(blocks(b"", 100).is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::io::Read;
+
+use csv::{ByteRecord, StringRecord};
+
+use crate::engine::{parse_timestamp, Transaction, TransactionType};
+
+/// Powers of ten exactly representable as f64.
+const POWERS_OF_TEN: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20,
+    1e21, 1e22,
+];
+/// Largest integer below which every integer is exactly representable as f64.
+const MAX_EXACT_MANTISSA: u64 = 1 << 53;
+/// Digits of the largest u64, longer integers are left to the standard library.
+const MAX_INTEGER_DIGITS: usize = 19;
+
+/// Parses an amount written `[-]digits[.digits]` as a fixed-point decimal, i.e. an integer mantissa and a number of decimals.
+///
+/// While the mantissa and the power of ten are exact as f64, their quotient is the f64 nearest to the amount,
+/// the same value the standard library parses. Other amounts, e.g. with an exponent, are left to the standard library.
+pub fn parse_amount(bytes: &[u8]) -> Option<f64> {
+    parse_fixed_point(bytes).or_else(|| std::str::from_utf8(bytes).ok()?.parse().ok())
+}
+
+fn parse_fixed_point(bytes: &[u8]) -> Option<f64> {
+    let (negative, digits) = match bytes.split_first() {
+        Some((b'-', digits)) => (true, digits),
+        _ => (false, bytes),
+    };
+    let mut mantissa: u64 = 0;
+    let mut decimals: Option<usize> = None;
+    let mut has_digits = false;
+    for &byte in digits {
+        match byte {
+            b'0'..=b'9' => {
+                mantissa = mantissa.checked_mul(10)?.checked_add(u64::from(byte - b'0'))?;
+                if let Some(decimals) = decimals.as_mut() {
+                    *decimals += 1;
+                }
+                has_digits = true;
+            }
+            b'.' if decimals.is_none() => decimals = Some(0),
+            _ => return None,
+        }
+    }
+    if !has_digits || mantissa > MAX_EXACT_MANTISSA {
+        return None;
+    }
+    let amount = mantissa as f64 / POWERS_OF_TEN.get(decimals.unwrap_or_default())?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Parses an unsigned integer written in plain digits.
+fn parse_integer<T: TryFrom<u64>>(bytes: &[u8]) -> Option<T> {
+    if bytes.is_empty() || bytes.len() > MAX_INTEGER_DIGITS || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = bytes.iter().fold(0u64, |value, byte| value * 10 + u64::from(byte - b'0'));
+    T::try_from(value).ok()
+}
+
+/// Parses an optional field, empty or missing fields being None.
+fn parse_optional<T>(bytes: Option<&[u8]>, parse: impl Fn(&[u8]) -> Option<T>) -> Option<Option<T>> {
+    match bytes {
+        None | Some([]) => Some(None),
+        Some(bytes) => parse(bytes).map(Some),
+    }
+}
+
+/// Position of the transaction fields among the columns.
+#[derive(Debug, Default)]
+struct Columns {
+    tx_type: usize,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+    operator: Option<usize>,
+    timestamp: Option<usize>,
+    seq: Option<usize>,
+    currency: Option<usize>,
+}
+
+impl Columns {
+    /// Maps the headers, None if a required column is missing or a column is repeated.
+    fn new(headers: &StringRecord) -> Option<Self> {
+        let position = |name: &str| {
+            let mut positions = headers.iter().enumerate().filter(|(_, header)| *header == name);
+            match (positions.next(), positions.next()) {
+                (Some((column, _)), None) => Some(Some(column)),
+                (None, _) => Some(None),
+                (Some(_), Some(_)) => None,
+            }
+        };
+        Some(Columns {
+            tx_type: position("type")??,
+            client: position("client")??,
+            tx: position("tx")??,
+            amount: position("amount")?,
+            operator: position("operator")?,
+            timestamp: position("timestamp")?,
+            seq: position("seq")?,
+            currency: position("currency")?,
+        })
+    }
+
+    /// Parses a row into the transaction, reusing its allocations.
+    /// Returns false, leaving the transaction untouched, if the row needs the serde path.
+    fn parse(&self, record: &ByteRecord, transaction: &mut Transaction) -> bool {
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column));
+        let parsed = (|| {
+            let tx_type = TransactionType::from_bytes(field(Some(self.tx_type))?)?;
+            let client = parse_integer(field(Some(self.client))?)?;
+            let tx = parse_integer(field(Some(self.tx))?)?;
+            let amount = parse_optional(field(self.amount), parse_amount)?;
+            let operator = parse_optional(field(self.operator), parse_integer)?;
+            let timestamp = parse_optional(field(self.timestamp), |bytes| {
+                parse_timestamp(std::str::from_utf8(bytes).ok()?).ok()
+            })?;
+            let seq = parse_optional(field(self.seq), parse_integer)?;
+            let currency = match field(self.currency) {
+                None | Some([]) => None,
+                Some(bytes) => Some(std::str::from_utf8(bytes).ok()?),
+            };
+            Some((tx_type, client, tx, amount, operator, timestamp, seq, currency))
+        })();
+        let Some((tx_type, client, tx, amount, operator, timestamp, seq, currency)) = parsed else {
+            return false;
+        };
+        transaction.tx_type = tx_type;
+        transaction.client = client;
+        transaction.tx = tx;
+        transaction.amount = amount;
+        transaction.operator = operator;
+        transaction.timestamp = timestamp;
+        transaction.seq = seq;
+        match (currency, transaction.currency.as_mut()) {
+            (Some(code), Some(reused)) => {
+                reused.clear();
+                reused.push_str(code);
+            }
+            (code, _) => transaction.currency = code.map(str::to_string),
+        }
+        true
+    }
+}
+
+/// Reads transactions from a CSV source without allocating per row.
+///
+/// Rows are read into a reused `ByteRecord` and parsed from bytes. Rows the fast path does not handle,
+/// e.g. an unknown type, an amount with an exponent or an invalid field, are deserialized with serde,
+/// so that the transactions read and the errors returned are the same as with `reader.deserialize()`.
+pub struct TransactionReader<R> {
+    reader: csv::Reader<R>,
+    record: ByteRecord,
+    headers: StringRecord,
+    /// None if the headers can't be mapped, every row then going through serde
+    columns: Option<Columns>,
+}
+
+impl<R: Read> TransactionReader<R> {
+    pub fn new(from: R) -> Result<Self, csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All) // In order to handle whitespaces
+            .from_reader(from);
+        let headers = reader.headers()?.clone();
+        Ok(TransactionReader {
+            reader,
+            record: ByteRecord::new(),
+            columns: Columns::new(&headers),
+            headers,
+        })
+    }
+
+    /// Reads the next row into the transaction, reusing its allocations.
+    ///
+    /// Returns false once the source is exhausted.
+    pub fn read(&mut self, transaction: &mut Transaction) -> Result<bool, Box<dyn Error>> {
+        if !self.reader.read_byte_record(&mut self.record)? {
+            return Ok(false);
+        }
+        if self.columns.as_ref().is_some_and(|columns| columns.parse(&self.record, transaction)) {
+            return Ok(true);
+        }
+        let record = StringRecord::from_byte_record(self.record.clone()).map_err(|err| {
+            // Same message as the error of the serde path, which can't be built outside of the csv crate
+            let position = self.record.position().cloned().unwrap_or_else(csv::Position::new);
+            format!(
+                "CSV parse error: record {} (line {}, field: {}, byte: {}): {}",
+                position.record(),
+                position.line(),
+                err.utf8_error().field(),
+                position.byte(),
+                err.utf8_error()
+            )
+        })?;
+        *transaction = record.deserialize(Some(&self.headers))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_parse_like_the_standard_library() {
+        for amount in ["0", "-0", "1", "1.", ".5", "0.1", "1.2345", "-2.5", "9007199254740993", "0.30000000000000004", "1e3", "inf"] {
+            assert_eq!(
+                parse_amount(amount.as_bytes()).map(f64::to_bits),
+                amount.parse::<f64>().ok().map(f64::to_bits),
+                "{amount}"
+            );
+        }
+        for amount in ["", ".", "-", "1.2.3", "1,5", "abc"] {
+            assert_eq!(parse_amount(amount.as_bytes()), None, "{amount}");
+        }
+        assert_eq!(TransactionType::from_bytes(b"chargeback").map(|tx_type| tx_type.as_str()), Some("chargeback"));
+        assert_eq!(TransactionType::from_bytes(b"Deposit"), None);
+    }
+}
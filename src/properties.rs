@@ -0,0 +1,148 @@
+//! Property-based tests generating arbitrary sequences of deposits, withdrawals, disputes, resolves and chargebacks.
+//!
+//! Amounts are whole numbers so that balances can be compared exactly. The invariant checker runs on every transaction.
+
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+
+use crate::engine::{ClientId, Engine, EngineConfig, Transaction, TransactionId, TransactionType};
+use crate::ledger::Account;
+
+const KINDS: [TransactionType; 5] = [
+    TransactionType::Deposit,
+    TransactionType::Withdrawal,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+];
+const MAX_TRANSACTIONS: usize = 64;
+
+/// Builds a transaction from generated values: deposits and withdrawals get the row position as tx id,
+/// other transactions reference any tx id, possibly unknown or belonging to another client.
+fn transaction(row: usize, (kind, client, amount, target): (usize, ClientId, u32, usize)) -> Transaction {
+    let references_history = kind >= 2;
+    Transaction {
+        tx_type: KINDS[kind],
+        client,
+        tx: if references_history { target as TransactionId + 1 } else { row as TransactionId + 1 },
+        amount: (!references_history).then_some(f64::from(amount)),
+        operator: None,
+        timestamp: None,
+        seq: None,
+        currency: None,
+    }
+}
+
+fn transactions() -> impl Strategy<Value = Vec<Transaction>> {
+    prop::collection::vec((0..KINDS.len(), 1..=3 as ClientId, 1..=100u32, 0..MAX_TRANSACTIONS), 0..MAX_TRANSACTIONS)
+        .prop_map(|rows| rows.into_iter().enumerate().map(|(row, values)| transaction(row, values)).collect())
+}
+
+/// Runs the transactions with the invariant checker on, failing on any violation.
+fn run(transactions: &[Transaction]) -> Engine {
+    let mut engine = Engine::new(EngineConfig { audit: true, ..Default::default() });
+    for transaction in transactions {
+        engine.handle_transaction(transaction);
+    }
+    assert_eq!(engine.violations, vec![]);
+    engine
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ModelClient {
+    available: f64,
+    held: f64,
+    locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModelState {
+    Undisputed,
+    Disputed,
+    ChargedBack,
+}
+
+/// Straightforward implementation of the specification with the default configuration.
+fn reference_model(transactions: &[Transaction]) -> HashMap<ClientId, ModelClient> {
+    let mut clients: HashMap<ClientId, ModelClient> = HashMap::new();
+    let mut history: HashMap<(ClientId, TransactionId), (f64, ModelState)> = HashMap::new();
+    for transaction in transactions {
+        let client = clients.entry(transaction.client).or_default();
+        let amount = transaction.amount.unwrap_or_default();
+        let referenced = history.get_mut(&(transaction.client, transaction.tx));
+        match (transaction.tx_type, referenced) {
+            (TransactionType::Deposit, _) if !client.locked => {
+                client.available += amount;
+                history.insert((transaction.client, transaction.tx), (amount, ModelState::Undisputed));
+            }
+            (TransactionType::Withdrawal, _) => {
+                if !client.locked && client.available >= amount {
+                    client.available -= amount;
+                }
+                history.insert((transaction.client, transaction.tx), (amount, ModelState::Undisputed));
+            }
+            (TransactionType::Dispute, Some((amount, state))) if !client.locked && *state == ModelState::Undisputed => {
+                client.available -= *amount;
+                client.held += *amount;
+                *state = ModelState::Disputed;
+            }
+            (TransactionType::Resolve, Some((amount, state))) if !client.locked && *state == ModelState::Disputed => {
+                client.held -= *amount;
+                client.available += *amount;
+                *state = ModelState::Undisputed;
+            }
+            (TransactionType::Chargeback, Some((amount, state))) if !client.locked && *state == ModelState::Disputed => {
+                client.held -= *amount;
+                client.locked = true;
+                *state = ModelState::ChargedBack;
+            }
+            _ => {}
+        }
+    }
+    clients
+}
+
+proptest! {
+    #[test]
+    fn money_is_conserved(transactions in transactions()) {
+        let engine = run(&transactions);
+
+        prop_assert!(engine.ledger.trial_balance().is_balanced());
+        let totals: f64 = engine.clients_state.values().map(|client_state| client_state.total).sum();
+        prop_assert_eq!(totals, engine.ledger.balance(Account::BankCash) + engine.ledger.balance(Account::Chargebacks));
+        for client_state in engine.clients_state.values() {
+            prop_assert_eq!(client_state.total, client_state.available + client_state.held);
+            prop_assert!(client_state.held >= 0.0);
+        }
+    }
+
+    #[test]
+    fn repeated_resolve_and_chargeback_are_idempotent(transactions in transactions()) {
+        let repeated: Vec<Transaction> = transactions
+            .iter()
+            .flat_map(|transaction| {
+                let times = if matches!(transaction.tx_type, TransactionType::Resolve | TransactionType::Chargeback) { 2 } else { 1 };
+                std::iter::repeat_n(transaction, times)
+            })
+            .cloned()
+            .collect();
+
+        prop_assert_eq!(run(&repeated).clients_state, run(&transactions).clients_state);
+    }
+
+    #[test]
+    fn engine_matches_reference_model(transactions in transactions()) {
+        let engine = run(&transactions);
+        let model = reference_model(&transactions);
+
+        prop_assert_eq!(engine.clients_state.len(), model.len());
+        for (client, expected) in model {
+            let client_state = &engine.clients_state[&client];
+            prop_assert_eq!(client_state.available, expected.available);
+            prop_assert_eq!(client_state.held, expected.held);
+            prop_assert_eq!(client_state.total, expected.available + expected.held);
+            prop_assert_eq!(client_state.status.is_locked(), expected.locked);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::engine::{ClientId, Engine, Transaction, TransactionId};
+
+/// Default maximum number of transactions held back by the buffer.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Transaction held back by the buffer, along with its position in the input.
+#[derive(Debug)]
+struct Pending {
+    keyed: bool,
+    arrival: u64,
+    transaction: Transaction,
+}
+
+/// Rows of a client held back by the buffer.
+#[derive(Debug, Default)]
+struct ClientQueue {
+    /// Order key and arrival of the rows, rows already released being skipped
+    rows: BinaryHeap<Reverse<(u64, u64)>>,
+    /// Highest key seen for the client
+    watermark: u64,
+}
+
+/// Bounded buffer restoring the order of each client's transactions before they reach the engine.
+///
+/// Transactions are ordered per client by sequence number, or by timestamp when there is none,
+/// and transactions of different clients are not ordered with respect to each other.
+/// A transaction is released once a transaction of the same client with a key at least `window` higher has been seen,
+/// or when more than `capacity` transactions are held back, parked ones included, the oldest key being released first.
+/// A transaction without a key is released right away if nothing is held back for its client,
+/// and otherwise queued after the client's transactions seen so far, so that it doesn't skip ahead of them.
+///
+/// Transactions referencing a tx id unknown to the engine (e.g. a dispute received before its deposit)
+/// are parked until the referenced transaction is handled, or until the window expires after they were parked:
+/// once the highest key seen for any client moved `window` further, or for transactions without a key,
+/// once `window` more transactions arrived.
+#[derive(Debug)]
+pub struct ReorderBuffer {
+    window: u64,
+    capacity: usize,
+    /// Transactions held back, identified by arrival
+    pending: HashMap<u64, Pending>,
+    queues: HashMap<ClientId, ClientQueue>,
+    /// Order key and arrival of every transaction held back, to release the oldest one when over capacity
+    by_key: BinaryHeap<Reverse<(u64, u64)>>,
+    /// Entries of `queues` and `by_key` left behind by released transactions
+    stale: usize,
+    /// Transactions parked on each tx id, along with the arrival of the first one, which identifies their expiry
+    parked: HashMap<(ClientId, TransactionId), (u64, Vec<Pending>)>,
+    /// Number of parked transactions
+    parked_len: usize,
+    /// Watermark at which the transactions parked on each tx id expire, with the arrival identifying them
+    parked_expiry: BinaryHeap<Reverse<(u64, u64, ClientId, TransactionId)>>,
+    /// Arrivals after which the transactions without a key parked on each tx id expire, with the arrival identifying them
+    parked_expiry_arrivals: BinaryHeap<Reverse<(u64, u64, ClientId, TransactionId)>>,
+    /// Highest key seen so far, for any client
+    watermark: u64,
+    arrivals: u64,
+}
+
+impl ReorderBuffer {
+    pub fn new(window: u64, capacity: usize) -> Self {
+        ReorderBuffer {
+            window,
+            capacity,
+            pending: HashMap::new(),
+            queues: HashMap::new(),
+            by_key: BinaryHeap::new(),
+            stale: 0,
+            parked: HashMap::new(),
+            parked_len: 0,
+            parked_expiry: BinaryHeap::new(),
+            parked_expiry_arrivals: BinaryHeap::new(),
+            watermark: 0,
+            arrivals: 0,
+        }
+    }
+
+    /// Adds a transaction to the buffer and hands every transaction that can no longer be reordered to the engine.
+    pub fn push(&mut self, transaction: Transaction, engine: &mut Engine) {
+        self.arrivals += 1;
+        let client = transaction.client;
+        let keyed = transaction.order_key().is_some();
+        let held_back = self.oldest(client).is_some();
+        let queue = self.queues.entry(client).or_default();
+        let key = transaction.order_key().unwrap_or(queue.watermark);
+        let pending = Pending { keyed, arrival: self.arrivals, transaction };
+        if !keyed && !held_back {
+            self.release(pending, engine);
+            self.expire(engine);
+            return;
+        }
+        queue.watermark = queue.watermark.max(key);
+        queue.rows.push(Reverse((key, self.arrivals)));
+        let client_watermark = queue.watermark;
+        self.watermark = self.watermark.max(key);
+        self.by_key.push(Reverse((key, self.arrivals)));
+        self.pending.insert(self.arrivals, pending);
+
+        while let Some((key, arrival)) = self.oldest(client) {
+            if key.saturating_add(self.window) > client_watermark {
+                break;
+            }
+            self.release_pending(arrival, engine);
+        }
+        while self.len() > self.capacity {
+            let Some(arrival) = self.oldest_overall() else { break };
+            self.release_pending(arrival, engine);
+        }
+        self.compact();
+        self.expire(engine);
+    }
+
+    /// Hands every remaining transaction to the engine, in order.
+    pub fn finish(&mut self, engine: &mut Engine) {
+        while let Some(arrival) = self.oldest_overall() {
+            self.release_pending(arrival, engine);
+        }
+        for queue in self.queues.values_mut() {
+            queue.rows.clear();
+        }
+        self.stale = 0;
+        let mut parked: Vec<_> = self.parked.drain().flat_map(|(_, (_, parked))| parked).collect();
+        parked.sort_by_key(|parked| parked.arrival);
+        for parked in parked {
+            engine.handle_transaction(&parked.transaction);
+        }
+        self.parked_len = 0;
+        self.parked_expiry.clear();
+        self.parked_expiry_arrivals.clear();
+    }
+
+    /// Number of transactions held back, parked ones included.
+    pub fn len(&self) -> usize {
+        self.pending.len() + self.parked_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Order key and arrival of the client's oldest transaction held back, dropping the entries of released ones.
+    fn oldest(&mut self, client: ClientId) -> Option<(u64, u64)> {
+        let queue = self.queues.get_mut(&client)?;
+        while let Some(&Reverse((key, arrival))) = queue.rows.peek() {
+            if self.pending.contains_key(&arrival) {
+                return Some((key, arrival));
+            }
+            queue.rows.pop();
+            self.stale -= 1;
+        }
+        None
+    }
+
+    /// Arrival of the transaction held back with the lowest key, whatever its client.
+    fn oldest_overall(&mut self) -> Option<u64> {
+        while let Some(&Reverse((_, arrival))) = self.by_key.peek() {
+            if self.pending.contains_key(&arrival) {
+                return Some(arrival);
+            }
+            self.by_key.pop();
+            self.stale -= 1;
+        }
+        None
+    }
+
+    fn release_pending(&mut self, arrival: u64, engine: &mut Engine) {
+        if let Some(pending) = self.pending.remove(&arrival) {
+            // Its entries in `queues` and `by_key` are dropped lazily
+            self.stale += 2;
+            self.release(pending, engine);
+        }
+    }
+
+    /// Drops the entries left behind by released transactions once they outnumber the transactions held back.
+    fn compact(&mut self) {
+        if self.stale <= self.pending.len().max(self.capacity) {
+            return;
+        }
+        let pending = &self.pending;
+        self.by_key.retain(|Reverse((_, arrival))| pending.contains_key(arrival));
+        for queue in self.queues.values_mut() {
+            queue.rows.retain(|Reverse((_, arrival))| pending.contains_key(arrival));
+        }
+        self.stale = 0;
+    }
+
+    fn release(&mut self, pending: Pending, engine: &mut Engine) {
+        let transaction = &pending.transaction;
+        let id = (transaction.client, transaction.tx);
+        if transaction.references_history() && !engine.transaction_history.contains_key(&id) {
+            let (first, parked) = self.parked.entry(id).or_insert_with(|| (pending.arrival, Vec::new()));
+            if parked.is_empty() && pending.keyed {
+                self.parked_expiry.push(Reverse((self.watermark.saturating_add(self.window), *first, id.0, id.1)));
+            } else if parked.is_empty() {
+                self.parked_expiry_arrivals.push(Reverse((pending.arrival.saturating_add(self.window), *first, id.0, id.1)));
+            }
+            parked.push(pending);
+            self.parked_len += 1;
+            return;
+        }
+        engine.handle_transaction(transaction);
+        if !transaction.references_history() && engine.transaction_history.contains_key(&id) {
+            self.unpark(id, engine);
+        }
+    }
+
+    /// Hands the transactions parked on a tx id to the engine.
+    fn unpark(&mut self, id: (ClientId, TransactionId), engine: &mut Engine) {
+        for parked in self.parked.remove(&id).map(|(_, parked)| parked).unwrap_or_default() {
+            self.parked_len -= 1;
+            engine.handle_transaction(&parked.transaction);
+        }
+    }
+
+    /// Tells whether the transactions parked on a tx id are the ones an expiry entry was pushed for,
+    /// and not a later group parked on the same tx id after they were handed to the engine.
+    fn parked_since(&self, id: (ClientId, TransactionId), arrival: u64) -> bool {
+        self.parked.get(&id).is_some_and(|(first, _)| *first == arrival)
+    }
+
+    /// Hands parked transactions whose window expired, or the oldest ones while the buffer is over capacity,
+    /// to the engine, which will ignore them.
+    fn expire(&mut self, engine: &mut Engine) {
+        while let Some(Reverse((deadline, first, client, tx))) = self.parked_expiry.peek().copied() {
+            if deadline > self.watermark && self.len() <= self.capacity {
+                break;
+            }
+            self.parked_expiry.pop();
+            if self.parked_since((client, tx), first) {
+                self.unpark((client, tx), engine);
+            }
+        }
+        while let Some(Reverse((deadline, first, client, tx))) = self.parked_expiry_arrivals.peek().copied() {
+            if deadline > self.arrivals && self.len() <= self.capacity {
+                break;
+            }
+            self.parked_expiry_arrivals.pop();
+            if self.parked_since((client, tx), first) {
+                self.unpark((client, tx), engine);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineConfig, TransactionType};
+
+    fn deposit(tx: TransactionId, seq: u64) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(1.0),
+            operator: None,
+            timestamp: None,
+            seq: Some(seq),
+            currency: None,
+        }
+    }
+
+    fn dispute(client: ClientId, tx: TransactionId) -> Transaction {
+        Transaction { tx_type: TransactionType::Dispute, client, tx, amount: None, seq: None, ..deposit(tx, 0) }
+    }
+
+    #[test]
+    fn buffer_releases_oldest_transaction_when_full() {
+        let mut engine = Engine::default();
+        let mut buffer = ReorderBuffer::new(100, 2);
+
+        buffer.push(deposit(1, 3), &mut engine);
+        buffer.push(deposit(2, 1), &mut engine);
+        assert!(engine.transaction_history.is_empty());
+
+        buffer.push(deposit(3, 2), &mut engine);
+        assert_eq!(buffer.len(), 2);
+        assert!(engine.transaction_history.contains_key(&(1, 2)));
+
+        buffer.finish(&mut engine);
+        assert!(buffer.is_empty());
+        assert_eq!(engine.clients_state[&1].total, 3.0);
+    }
+
+    #[test]
+    fn transactions_without_key_keep_their_place_and_expire() {
+        let mut engine = Engine::new(EngineConfig { journal: true, ..Default::default() });
+        let mut buffer = ReorderBuffer::new(10, 3);
+
+        buffer.push(deposit(1, 5), &mut engine);
+        buffer.push(Transaction { seq: None, ..deposit(2, 0) }, &mut engine);
+        buffer.push(deposit(3, 3), &mut engine);
+        buffer.push(deposit(4, 20), &mut engine);
+        let handled: Vec<TransactionId> = engine.journal.iter().map(|entry| entry.tx).collect();
+        assert_eq!(handled, vec![3, 1, 2]);
+
+        // Parked transactions count toward the capacity
+        buffer.push(dispute(5, 9), &mut engine);
+        buffer.push(dispute(6, 9), &mut engine);
+        buffer.push(dispute(7, 9), &mut engine);
+        assert_eq!(buffer.len(), 3);
+        buffer.push(Transaction { client: 8, ..deposit(5, 40) }, &mut engine);
+        assert!(buffer.len() <= 3);
+
+        // A parked transaction without a key expires after window more arrivals
+        buffer.finish(&mut engine);
+        buffer.push(dispute(9, 9), &mut engine);
+        assert_eq!(buffer.len(), 1);
+        for tx in 10..20 {
+            buffer.push(Transaction { client: 10, seq: None, ..deposit(tx, 0) }, &mut engine);
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn clients_are_ordered_independently() {
+        let mut engine = Engine::default();
+        let mut buffer = ReorderBuffer::new(10, 100);
+
+        buffer.push(Transaction { client: 1, ..deposit(1, 100) }, &mut engine);
+        // Far behind client 1, but client 2 has not moved past the window yet
+        buffer.push(Transaction { client: 2, tx_type: TransactionType::Withdrawal, ..deposit(3, 12) }, &mut engine);
+        buffer.push(Transaction { client: 2, amount: Some(2.0), ..deposit(2, 11) }, &mut engine);
+        assert!(!engine.clients_state.contains_key(&2));
+
+        buffer.push(Transaction { client: 2, ..deposit(4, 30) }, &mut engine);
+        assert_eq!(engine.clients_state[&2].total, 1.0);
+        buffer.finish(&mut engine);
+        assert_eq!(engine.clients_state[&2].total, 2.0);
+    }
+
+    #[test]
+    fn expiry_of_an_unparked_group_is_ignored() {
+        let mut engine = Engine::default();
+        let mut buffer = ReorderBuffer::new(10, 100);
+
+        buffer.push(dispute(1, 9), &mut engine);
+        // Handed to the engine before its window expired, its expiry stays behind
+        buffer.unpark((1, 9), &mut engine);
+        for tx in 1..5 {
+            buffer.push(Transaction { client: 2, seq: None, ..deposit(tx, 0) }, &mut engine);
+        }
+        buffer.push(dispute(1, 9), &mut engine);
+        for tx in 5..10 {
+            buffer.push(Transaction { client: 2, seq: None, ..deposit(tx, 0) }, &mut engine);
+        }
+        assert_eq!(buffer.len(), 1);
+
+        for tx in 10..15 {
+            buffer.push(Transaction { client: 2, seq: None, ..deposit(tx, 0) }, &mut engine);
+        }
+        assert!(buffer.is_empty());
+    }
+}
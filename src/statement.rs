@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::engine::{ClientId, ClientState, Engine, JournalEntry, Timestamp, Transaction, TransactionId, TransactionType};
+use crate::interest::{business_day, day_end};
+
+/// Maximum difference tolerated when reconciling, amounts being floats.
+const TOLERANCE: f64 = 1e-9;
+
+/// Represents a transaction applied to a client during the day.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    pub tx: TransactionId,
+    /// Type of the transaction, or "clear", "resolve", "chargeback", "interest" for changes made by the engine
+    pub kind: String,
+    pub timestamp: Option<Timestamp>,
+    /// Movement of available, fees included
+    pub available: f64,
+    /// Movement of held
+    pub held: f64,
+    /// Movement of total, fees included
+    pub total: f64,
+    /// Fees charged on the transaction
+    pub fees: f64,
+}
+
+/// Represents the activity of a client over a business day.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub client: ClientId,
+    /// Business day counted in days since the Unix epoch, None when days are only closed by `eod` rows
+    pub day: Option<u64>,
+    pub opening: ClientState,
+    pub lines: Vec<StatementLine>,
+    /// Fees charged during the day
+    pub fees: f64,
+    pub disputes_opened: usize,
+    /// Disputes resolved or charged back during the day
+    pub disputes_closed: usize,
+    pub closing: ClientState,
+}
+
+impl Statement {
+    fn new(client: ClientId, day: Option<u64>, opening: ClientState) -> Self {
+        Statement {
+            client,
+            day,
+            opening,
+            lines: Vec::new(),
+            fees: 0.0,
+            disputes_opened: 0,
+            disputes_closed: 0,
+            closing: opening,
+        }
+    }
+
+    fn add(&mut self, entry: JournalEntry) {
+        let (before, after) = (entry.before, entry.after);
+        let line = StatementLine {
+            tx: entry.tx,
+            kind: entry.kind,
+            timestamp: entry.timestamp,
+            available: after.available - before.available,
+            held: after.held - before.held,
+            total: after.total - before.total,
+            fees: after.fees - before.fees,
+        };
+        match line.kind.as_str() {
+            "dispute" => self.disputes_opened += 1,
+            "resolve" | "chargeback" if line.held < 0.0 => self.disputes_closed += 1,
+            _ => {}
+        }
+        self.fees += line.fees;
+        self.closing = after;
+        self.lines.push(line);
+    }
+
+    /// Net movement of the client's total over the day.
+    pub fn net_movement(&self) -> f64 {
+        self.lines.iter().map(|line| line.total).sum()
+    }
+
+    /// Checks that the movements of the day account for the change between opening and closing balances.
+    pub fn reconciles(&self) -> bool {
+        let (available, held, total) = self.lines.iter().fold((0.0, 0.0, 0.0), |(available, held, total), line| {
+            (available + line.available, held + line.held, total + line.total)
+        });
+        (self.opening.available + available - self.closing.available).abs() < TOLERANCE
+            && (self.opening.held + held - self.closing.held).abs() < TOLERANCE
+            && (self.opening.total + total - self.closing.total).abs() < TOLERANCE
+    }
+}
+
+/// Batch process closing each business day on top of the engine, producing a statement per client active that day.
+///
+/// With timestamps, a business day ends `cutoff` seconds after midnight (UTC). An `eod` row closes the current day.
+/// The engine must journal its changes (see `EngineConfig::journal`).
+#[derive(Debug, Default)]
+pub struct DailyClose {
+    cutoff: Timestamp,
+    /// Business day currently open, known once a timestamp has been seen
+    day: Option<u64>,
+    open: BTreeMap<ClientId, Statement>,
+    /// Statements of the closed days, in order of day then client
+    pub statements: Vec<Statement>,
+}
+
+impl DailyClose {
+    pub fn new(cutoff: Timestamp) -> Self {
+        DailyClose {
+            cutoff,
+            ..Default::default()
+        }
+    }
+
+    /// Hands a transaction to the engine, closing the days that ended before it.
+    pub fn push(&mut self, transaction: &Transaction, engine: &mut Engine) {
+        if let Some(timestamp) = transaction.timestamp {
+            let today = business_day(timestamp, self.cutoff);
+            let open_day = *self.day.get_or_insert(today);
+            for day in open_day..today {
+                // The interest of the day is posted at its end, and belongs to its statement
+                engine.advance_time(day_end(day, self.cutoff) - 1);
+                engine.close_days_ended(day_end(day, self.cutoff));
+                self.collect(engine);
+                self.close();
+            }
+        }
+        engine.handle_transaction(transaction);
+        self.collect(engine);
+        if transaction.tx_type == TransactionType::Eod {
+            self.close();
+        }
+    }
+
+    /// Closes the current day.
+    pub fn finish(&mut self, engine: &mut Engine) {
+        self.collect(engine);
+        self.close();
+    }
+
+    fn collect(&mut self, engine: &mut Engine) {
+        for entry in engine.journal.drain(..) {
+            self.open
+                .entry(entry.client)
+                .or_insert_with(|| Statement::new(entry.client, self.day, entry.before))
+                .add(entry);
+        }
+    }
+
+    fn close(&mut self) {
+        self.statements.extend(std::mem::take(&mut self.open).into_values());
+        self.day = self.day.map(|day| day + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statement_counts_disputes_and_reconciles() {
+        let opening = ClientState { available: 10.0, total: 10.0, ..Default::default() };
+        let disputed = ClientState { available: 6.0, held: 4.0, total: 10.0, ..Default::default() };
+        let charged_back = ClientState { available: 6.0, total: 6.0, ..Default::default() };
+        let mut statement = Statement::new(1, Some(0), opening);
+        for (kind, before, after) in [("dispute", opening, disputed), ("chargeback", disputed, charged_back)] {
+            statement.add(JournalEntry { client: 1, tx: 1, kind: kind.to_string(), timestamp: None, before, after });
+        }
+
+        assert_eq!((statement.disputes_opened, statement.disputes_closed), (1, 1));
+        assert_eq!(statement.net_movement(), -4.0);
+        assert!(statement.reconciles());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::TransactionType;
+
+/// Status of a client's account, deciding which operations are accepted.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    /// Withdrawals are blocked but deposits are still accepted
+    Frozen,
+    /// Set by a chargeback, no client operation is accepted
+    Locked,
+    /// The account has been paid out and retired
+    Closed,
+}
+
+/// Client-originated operation on an account.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Refund,
+}
+
+impl Operation {
+    /// Operation done by a transaction of the given type, None for types that are not client operations.
+    pub fn from_tx_type(tx_type: TransactionType) -> Option<Self> {
+        match tx_type {
+            TransactionType::Deposit => Some(Operation::Deposit),
+            TransactionType::Withdrawal => Some(Operation::Withdrawal),
+            TransactionType::Dispute => Some(Operation::Dispute),
+            TransactionType::Resolve => Some(Operation::Resolve),
+            TransactionType::Chargeback => Some(Operation::Chargeback),
+            TransactionType::Refund => Some(Operation::Refund),
+            _ => None,
+        }
+    }
+}
+
+impl AccountStatus {
+    /// Default permission of the operation on an account with this status.
+    fn allows_by_default(&self, operation: Operation) -> bool {
+        match self {
+            AccountStatus::Active => true,
+            AccountStatus::Frozen => !matches!(operation, Operation::Withdrawal | Operation::Refund),
+            AccountStatus::Locked | AccountStatus::Closed => false,
+        }
+    }
+
+    /// Value of the `locked` output column.
+    pub fn is_locked(&self) -> bool {
+        matches!(self, AccountStatus::Locked | AccountStatus::Closed)
+    }
+
+    /// Value of the `status` output column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Frozen => "frozen",
+            AccountStatus::Locked => "locked",
+            AccountStatus::Closed => "closed",
+        }
+    }
+}
+
+/// Represents a line of the permission matrix configuration.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Permission {
+    pub status: AccountStatus,
+    pub operation: Operation,
+    pub allowed: bool,
+}
+
+/// Tells which operations are accepted for each account status.
+///
+/// Permissions not overridden fall back to the defaults:
+/// active accounts accept everything, frozen accounts accept everything but withdrawals and refunds,
+/// locked and closed accounts accept nothing.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PermissionMatrix {
+    overrides: HashMap<(AccountStatus, Operation), bool>,
+}
+
+impl PermissionMatrix {
+    pub fn new(permissions: impl IntoIterator<Item = Permission>) -> Self {
+        PermissionMatrix {
+            overrides: permissions
+                .into_iter()
+                .map(|permission| ((permission.status, permission.operation), permission.allowed))
+                .collect(),
+        }
+    }
+
+    /// Tells whether the operation is accepted on an account with the given status.
+    pub fn allows(&self, status: AccountStatus, operation: Operation) -> bool {
+        self.overrides
+            .get(&(status, operation))
+            .copied()
+            .unwrap_or_else(|| status.allows_by_default(operation))
+    }
+}